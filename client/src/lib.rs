@@ -1,9 +1,26 @@
 use wasm_bindgen::prelude::*;
-use web_sys::{CanvasRenderingContext2d, HtmlCanvasElement, MouseEvent};
-use js_sys::{Array, Object, Reflect};
+use web_sys::{CanvasRenderingContext2d, HtmlCanvasElement, ImageData, MouseEvent, Touch, TouchEvent};
+use js_sys::{Array, Function, Object, Reflect};
 use serde::{Serialize, Deserialize};
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
 use std::f64::consts::PI;
 
+// Geometry of a troop's outline, kept apart from the canvas calls that trace it
+#[derive(Debug, PartialEq)]
+enum ShapePath {
+    Circle { center: (f64, f64), radius: f64 },
+    Rect { origin: (f64, f64), size: (f64, f64) },
+    Triangle([(f64, f64); 3]),
+    Quad([(f64, f64); 4]),
+}
+
+// Radial command menu entries, clockwise starting from the right
+const COMMAND_MENU_SLICES: [&str; 4] = ["move", "attack", "stop", "hold"];
+
+// Smallest grid spacing in world units accepted by set_grid_size
+const MIN_GRID_SIZE: f64 = 1.0;
+
 // Game state types
 #[derive(Serialize, Deserialize, Clone)]
 pub struct Player {
@@ -46,6 +63,33 @@ pub struct Troop {
     max_range: Option<f64>,
     #[serde(default)]
     target: Option<u32>,
+    #[serde(default)]
+    target_position: Option<(f64, f64)>,
+    #[serde(default)]
+    max_health: Option<f64>,
+    #[serde(default)]
+    width: Option<f64>,
+    #[serde(default)]
+    height: Option<f64>,
+    #[serde(default)]
+    is_stealthed: bool,
+    #[serde(default)]
+    render_size: Option<f64>,
+    #[serde(default)]
+    rank: Option<u8>,
+    #[serde(default)]
+    name: Option<String>,
+}
+
+impl Troop {
+    fn health_fraction(&self) -> f64 {
+        (self.health / self.max_health.unwrap_or(100.0)).clamp(0.0, 1.0)
+    }
+    
+    fn size(&self) -> f64 {
+        // Non-positive sizes would collapse hit-testing and shapes to nothing
+        self.render_size.filter(|s| *s > 0.0).unwrap_or(10.0)
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -58,14 +102,61 @@ pub struct Projectile {
     damage: f64,
     time_to_live: f64,
     color: (u8, u8, u8),
+    // Lobbed projectiles fly along a parabola peaking at this height
+    #[serde(default)]
+    arc_height: Option<f64>,
+    #[serde(default)]
+    max_time_to_live: Option<f64>,
 }
 
-#[derive(Serialize, Deserialize)]
+impl Projectile {
+    fn flight_fraction(&self) -> f64 {
+        (1.0 - self.time_to_live / self.max_time_to_live.unwrap_or(2.0)).clamp(0.0, 1.0)
+    }
+    
+    // Height above the ground and its rate of change per second along the arc
+    fn arc(&self, arc_height: f64) -> (f64, f64) {
+        let t = self.flight_fraction();
+        let height = 4.0 * arc_height * t * (1.0 - t);
+        let climb_rate = 4.0 * arc_height * (1.0 - 2.0 * t) / self.max_time_to_live.unwrap_or(2.0);
+        (height, climb_rate)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
 pub struct GameState {
     players: Vec<Player>,
     troops: Vec<Troop>,
     projectiles: Vec<Projectile>,
     map_size: (f64, f64),
+    // Impassable areas as (x, y, width, height) rectangles
+    #[serde(default)]
+    obstacles: Vec<(f64, f64, f64, f64)>,
+}
+
+impl GameState {
+    // Drop entities with non-finite numbers and clear bad directions, returning how many were dropped
+    fn sanitize(&mut self) -> usize {
+        let finite = |(x, y): (f64, f64)| x.is_finite() && y.is_finite();
+        let before = self.troops.len() + self.projectiles.len();
+        
+        self.troops.retain(|t| finite(t.position) && t.speed.is_finite() && t.health.is_finite());
+        self.projectiles.retain(|p| finite(p.position) && p.speed.is_finite());
+        
+        // A bad direction only affects facing, so keep the entity and treat it as stationary
+        for troop in &mut self.troops {
+            if !finite(troop.direction) {
+                troop.direction = (0.0, 0.0);
+            }
+        }
+        for projectile in &mut self.projectiles {
+            if !finite(projectile.direction) {
+                projectile.direction = (0.0, 0.0);
+            }
+        }
+        
+        before - self.troops.len() - self.projectiles.len()
+    }
 }
 
 #[derive(Serialize, Deserialize)]
@@ -76,6 +167,448 @@ pub struct DevData {
     troops_by_player: Object,
 }
 
+// Short-lived ring drawn where a projectile disappeared between snapshots
+struct ImpactMarker {
+    position: (f64, f64),
+    color: (u8, u8, u8),
+    created_at: f64,
+}
+
+// Floating number shown where a troop lost health between snapshots
+struct DamageNumber {
+    position: (f64, f64),
+    amount: f64,
+    created_at: f64,
+}
+
+// Brief line and pulse confirming where a move order was sent
+struct MoveConfirmation {
+    origin: (f64, f64),
+    target: (f64, f64),
+    created_at: f64,
+}
+
+// Eased camera move between two (center_x, center_y, zoom) views
+struct CameraAnimation {
+    from: (f64, f64, f64),
+    to: (f64, f64, f64),
+    started_at: f64,
+}
+
+// A troop's orders from just before the last command, so it can be undone
+struct PreviousOrder {
+    troop_id: u32,
+    target_position: (f64, f64),
+    target: Option<u32>,
+}
+
+// Camera pose plus the view size in CSS pixels, so coordinate math doesn't touch the canvas
+#[derive(Clone, Copy)]
+struct Camera {
+    x: f64,
+    y: f64,
+    zoom: f64,
+    rotation: f64,
+    view_width: f64,
+    view_height: f64,
+}
+
+impl Camera {
+    fn screen_to_world(&self, x: f64, y: f64) -> (f64, f64) {
+        // Undo the rotation around the canvas center, then the zoom and pan
+        let center_x = self.view_width / 2.0;
+        let center_y = self.view_height / 2.0;
+        let (x, y) = rotate_point((x - center_x, y - center_y), -self.rotation);
+        
+        ((x + center_x) / self.zoom + self.x, (y + center_y) / self.zoom + self.y)
+    }
+    
+    fn world_to_screen(&self, x: f64, y: f64) -> (f64, f64) {
+        let center_x = self.view_width / 2.0;
+        let center_y = self.view_height / 2.0;
+        let unrotated_x = (x - self.x) * self.zoom - center_x;
+        let unrotated_y = (y - self.y) * self.zoom - center_y;
+        let (x, y) = rotate_point((unrotated_x, unrotated_y), self.rotation);
+        
+        (x + center_x, y + center_y)
+    }
+    
+    fn is_on_screen(&self, x: f64, y: f64) -> bool {
+        // Exact test against the canvas, unlike the bounding box when rotated
+        in_rect(self.world_to_screen(x, y), (0.0, 0.0), (self.view_width, self.view_height))
+    }
+    
+    fn visible_world_corners(&self) -> [(f64, f64); 4] {
+        // Canvas corners in world coordinates, clockwise from the top left
+        [
+            self.screen_to_world(0.0, 0.0),
+            self.screen_to_world(self.view_width, 0.0),
+            self.screen_to_world(self.view_width, self.view_height),
+            self.screen_to_world(0.0, self.view_height),
+        ]
+    }
+    
+    fn visible_world_rect(&self) -> (f64, f64, f64, f64) {
+        // Bounding box of the visible area in world coordinates as (min_x, min_y, max_x, max_y)
+        let corners = self.visible_world_corners();
+        let xs = corners.iter().map(|corner| corner.0);
+        let ys = corners.iter().map(|corner| corner.1);
+        
+        (
+            xs.clone().fold(f64::INFINITY, f64::min),
+            ys.clone().fold(f64::INFINITY, f64::min),
+            xs.fold(f64::NEG_INFINITY, f64::max),
+            ys.fold(f64::NEG_INFINITY, f64::max),
+        )
+    }
+}
+
+// Extra canvas drawn with its own camera, e.g. an overview next to the main view
+struct SecondaryView {
+    canvas: HtmlCanvasElement,
+    context: CanvasRenderingContext2d,
+    // Each view keeps its own fog overlay so differently sized views don't
+    // reallocate a shared one every frame
+    fog_canvas: HtmlCanvasElement,
+    fog_context: CanvasRenderingContext2d,
+    camera_x: f64,
+    camera_y: f64,
+    zoom: f64,
+    camera_rotation: f64,
+    pixel_ratio: f64,
+    // Refit the whole map every frame until the camera is set explicitly
+    fit_map: bool,
+}
+
+// Short-lived marker drawn where a troop disappeared between snapshots
+struct DeathMarker {
+    position: (f64, f64),
+    color: (u8, u8, u8),
+    created_at: f64,
+}
+
+// Counts gathered while rendering a frame, shown in the dev tools panel
+#[derive(Clone, Copy, Default)]
+struct RenderStats {
+    troops_drawn: usize,
+    troops_culled: usize,
+    projectiles_drawn: usize,
+    projectiles_culled: usize,
+    draw_calls: usize,
+}
+
+// Public stats of a single troop, as shown in inspection panels
+#[derive(Serialize)]
+struct TroopStats {
+    id: u32,
+    player_id: u32,
+    #[serde(rename = "type")]
+    unit_type: String,
+    health: f64,
+    max_health: f64,
+    attack: f64,
+}
+
+impl TroopStats {
+    fn from_troop(troop: &Troop) -> TroopStats {
+        TroopStats {
+            id: troop.id,
+            player_id: troop.player_id,
+            unit_type: troop.unit_type.clone(),
+            health: troop.health,
+            max_health: troop.max_health.unwrap_or(100.0),
+            attack: troop.attack,
+        }
+    }
+}
+
+// View settings the host page can persist; missing fields keep their current values
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct RendererSettings {
+    camera_x: Option<f64>,
+    camera_y: Option<f64>,
+    zoom: Option<f64>,
+    show_dev_tools: Option<bool>,
+    fog_enabled: Option<bool>,
+    vision_radius: Option<f64>,
+    show_minimap: Option<bool>,
+    show_ranges: Option<bool>,
+    edge_scroll_enabled: Option<bool>,
+    edge_scroll_margin: Option<f64>,
+    iff_enabled: Option<bool>,
+    direction_line_length: Option<f64>,
+    direction_line_color: Option<String>,
+    show_trails: Option<bool>,
+    snap_to_grid: Option<bool>,
+    formation_moves: Option<bool>,
+    show_paths: Option<bool>,
+    health_shading: Option<bool>,
+    show_grid_labels: Option<bool>,
+    camera_rotation: Option<f64>,
+    detection_radius: Option<f64>,
+    show_troop_ids: Option<bool>,
+    show_offscreen_indicators: Option<bool>,
+    show_grid: Option<bool>,
+    grid_size: Option<f64>,
+    selected_brightness: Option<f64>,
+    show_attack_lines: Option<bool>,
+    low_health_warning: Option<bool>,
+    low_health_warning_threshold: Option<f64>,
+    show_scale_bar: Option<bool>,
+    show_stack_counts: Option<bool>,
+    show_names: Option<bool>,
+    click_radius: Option<f64>,
+    quality: Option<u8>,
+    spread_crowds: Option<bool>,
+    colorblind_mode: Option<String>,
+    selection_mode: Option<String>,
+    render_style: Option<String>,
+    health_bar_mode: Option<String>,
+    grid_type: Option<String>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum ColorblindMode {
+    None,
+    Protanopia,
+    Deuteranopia,
+    Tritanopia,
+}
+
+impl ColorblindMode {
+    fn from_name(name: &str) -> Option<ColorblindMode> {
+        match name {
+            "none" => Some(ColorblindMode::None),
+            "protanopia" => Some(ColorblindMode::Protanopia),
+            "deuteranopia" => Some(ColorblindMode::Deuteranopia),
+            "tritanopia" => Some(ColorblindMode::Tritanopia),
+            _ => None,
+        }
+    }
+    
+    fn name(&self) -> &'static str {
+        match self {
+            ColorblindMode::None => "none",
+            ColorblindMode::Protanopia => "protanopia",
+            ColorblindMode::Deuteranopia => "deuteranopia",
+            ColorblindMode::Tritanopia => "tritanopia",
+        }
+    }
+}
+
+// Named spawn order registered from JavaScript; missing fields use the basic template
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+struct SpawnTemplate {
+    #[serde(rename = "type")]
+    unit_type: String,
+    count: u32,
+    // Formation the server places the spawned troops in, e.g. "cluster" or "line"
+    spread: String,
+}
+
+impl Default for SpawnTemplate {
+    fn default() -> SpawnTemplate {
+        SpawnTemplate {
+            unit_type: "basic".to_string(),
+            count: 15,
+            spread: "cluster".to_string(),
+        }
+    }
+}
+
+// Colors for the map background and grid, set from JavaScript as CSS color strings
+#[derive(Deserialize)]
+#[serde(default)]
+struct Theme {
+    background: String,
+    grid_minor: String,
+    grid_major: String,
+    border: String,
+    out_of_bounds: String,
+}
+
+impl Default for Theme {
+    fn default() -> Theme {
+        Theme {
+            background: "#222222".to_string(),
+            grid_minor: "#444444".to_string(),
+            grid_major: "#555555".to_string(),
+            border: "#888888".to_string(),
+            out_of_bounds: "#151515".to_string(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum SelectionMode {
+    Box,
+    Circle,
+}
+
+impl SelectionMode {
+    // Screen-space area of the shape dragged from start to end
+    fn area(&self, start: (f64, f64), end: (f64, f64)) -> f64 {
+        match self {
+            SelectionMode::Box => ((end.0 - start.0) * (end.1 - start.1)).abs(),
+            SelectionMode::Circle => PI * ((end.0 - start.0).powi(2) + (end.1 - start.1).powi(2)),
+        }
+    }
+    
+    fn contains(&self, start: (f64, f64), end: (f64, f64), point: (f64, f64)) -> bool {
+        match self {
+            SelectionMode::Box => in_rect(
+                point,
+                (start.0.min(end.0), start.1.min(end.1)),
+                (start.0.max(end.0), start.1.max(end.1)),
+            ),
+            SelectionMode::Circle => {
+                let radius_squared = (end.0 - start.0).powi(2) + (end.1 - start.1).powi(2);
+                (point.0 - start.0).powi(2) + (point.1 - start.1).powi(2) <= radius_squared
+            },
+        }
+    }
+    
+    fn from_name(name: &str) -> Option<SelectionMode> {
+        match name {
+            "box" => Some(SelectionMode::Box),
+            "circle" => Some(SelectionMode::Circle),
+            _ => None,
+        }
+    }
+    
+    fn name(&self) -> &'static str {
+        match self {
+            SelectionMode::Box => "box",
+            SelectionMode::Circle => "circle",
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum RenderStyle {
+    Filled,
+    Outlined,
+}
+
+impl RenderStyle {
+    fn from_name(name: &str) -> Option<RenderStyle> {
+        match name {
+            "filled" => Some(RenderStyle::Filled),
+            "outlined" => Some(RenderStyle::Outlined),
+            _ => None,
+        }
+    }
+    
+    fn name(&self) -> &'static str {
+        match self {
+            RenderStyle::Filled => "filled",
+            RenderStyle::Outlined => "outlined",
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum HealthBarMode {
+    Always,
+    DamagedOrSelected,
+    Never,
+}
+
+impl HealthBarMode {
+    fn from_name(name: &str) -> Option<HealthBarMode> {
+        match name {
+            "always" => Some(HealthBarMode::Always),
+            "damaged_or_selected" => Some(HealthBarMode::DamagedOrSelected),
+            "never" => Some(HealthBarMode::Never),
+            _ => None,
+        }
+    }
+    
+    fn name(&self) -> &'static str {
+        match self {
+            HealthBarMode::Always => "always",
+            HealthBarMode::DamagedOrSelected => "damaged_or_selected",
+            HealthBarMode::Never => "never",
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum GridType {
+    Square,
+    Hex,
+}
+
+impl GridType {
+    fn from_name(name: &str) -> Option<GridType> {
+        match name {
+            "square" => Some(GridType::Square),
+            "hex" => Some(GridType::Hex),
+            _ => None,
+        }
+    }
+    
+    fn name(&self) -> &'static str {
+        match self {
+            GridType::Square => "square",
+            GridType::Hex => "hex",
+        }
+    }
+}
+
+// Coarse spatial hash of troop indices for fast hit-testing
+struct TroopIndex {
+    cell_size: f64,
+    cells: HashMap<(i64, i64), Vec<usize>>,
+    max_troop_size: f64,
+}
+
+impl TroopIndex {
+    fn new(cell_size: f64) -> TroopIndex {
+        TroopIndex {
+            cell_size,
+            cells: HashMap::new(),
+            max_troop_size: 10.0,
+        }
+    }
+    
+    fn cell_of(&self, x: f64, y: f64) -> (i64, i64) {
+        ((x / self.cell_size).floor() as i64, (y / self.cell_size).floor() as i64)
+    }
+    
+    fn rebuild(&mut self, troops: &[Troop]) {
+        self.rebuild_from(troops.iter());
+    }
+    
+    fn rebuild_from<'a>(&mut self, troops: impl Iterator<Item = &'a Troop>) {
+        self.cells.clear();
+        self.max_troop_size = 10.0;
+        for (i, troop) in troops.enumerate() {
+            let cell = self.cell_of(troop.position.0, troop.position.1);
+            self.cells.entry(cell).or_default().push(i);
+            self.max_troop_size = self.max_troop_size.max(troop.size());
+        }
+    }
+    
+    // Indices of troops in every cell overlapping the square of the given radius around a point
+    fn query(&self, x: f64, y: f64, radius: f64) -> Vec<usize> {
+        let (min_cell_x, min_cell_y) = self.cell_of(x - radius, y - radius);
+        let (max_cell_x, max_cell_y) = self.cell_of(x + radius, y + radius);
+        
+        let mut indices = Vec::new();
+        for cell_x in min_cell_x..=max_cell_x {
+            for cell_y in min_cell_y..=max_cell_y {
+                if let Some(cell) = self.cells.get(&(cell_x, cell_y)) {
+                    indices.extend_from_slice(cell);
+                }
+            }
+        }
+        indices
+    }
+}
+
 // Renderer
 #[wasm_bindgen]
 pub struct Renderer {
@@ -91,9 +624,104 @@ pub struct Renderer {
     game_state: Option<GameState>,
     dev_data: Option<DevData>,
     show_dev_tools: bool,
+    // Selection box and ruler endpoints, in screen space so they follow the cursor if the camera moves
     selection_start: Option<(f64, f64)>,
     selection_end: Option<(f64, f64)>,
     selected_troops: Vec<u32>,
+    control_groups: HashMap<u8, Vec<u32>>,
+    fog_enabled: bool,
+    vision_radius: f64,
+    fog_canvas: HtmlCanvasElement,
+    fog_context: CanvasRenderingContext2d,
+    show_minimap: bool,
+    selection_callback: Option<Function>,
+    frame_times: VecDeque<f64>,
+    last_frame_time: Option<f64>,
+    attack_move_modifier: bool,
+    show_ranges: bool,
+    edge_scroll_enabled: bool,
+    edge_scroll_margin: f64,
+    iff_enabled: bool,
+    paused: bool,
+    buffered_state: Option<GameState>,
+    // Whether the displayed state is a replay snapshot picked by seek
+    seeking: bool,
+    direction_line_length: f64,
+    direction_line_color: String,
+    spawn_unit_type: String,
+    active_touches: HashMap<i32, (f64, f64)>,
+    touch_moved: bool,
+    pinch_distance: Option<f64>,
+    show_trails: bool,
+    snap_to_grid: bool,
+    formation_moves: bool,
+    mouse_world_position: Option<(f64, f64)>,
+    troop_index: TroopIndex,
+    // The state drawn this frame when interpolating, indexed like the latest one
+    interpolated: Option<GameState>,
+    interpolated_index: TroopIndex,
+    show_paths: bool,
+    colorblind_mode: ColorblindMode,
+    health_shading: bool,
+    waypoints: Vec<(f64, f64)>,
+    waypoint_troops: Vec<u32>,
+    ruler_enabled: bool,
+    show_grid_labels: bool,
+    previous_state: Option<GameState>,
+    previous_state_time: f64,
+    game_state_time: f64,
+    death_markers: Vec<DeathMarker>,
+    hovered_troop: Option<u32>,
+    camera_rotation: f64,
+    detection_radius: f64,
+    render_stats: Cell<RenderStats>,
+    min_zoom: f64,
+    max_zoom: f64,
+    pixel_ratio: f64,
+    show_troop_ids: bool,
+    camera_animation: Option<CameraAnimation>,
+    show_offscreen_indicators: bool,
+    impact_markers: Vec<ImpactMarker>,
+    grid_size: f64,
+    show_grid: bool,
+    snapshots: VecDeque<(f64, GameState)>,
+    troop_angles: RefCell<HashMap<u32, f64>>,
+    event_log: VecDeque<(String, f64)>,
+    selection_mode: SelectionMode,
+    move_confirmation: Option<MoveConfirmation>,
+    selected_brightness: f64,
+    damage_numbers: Vec<DamageNumber>,
+    render_style: RenderStyle,
+    rally_point: Option<(f64, f64)>,
+    highlighted: Vec<u32>,
+    rejected_entities: usize,
+    show_attack_lines: bool,
+    theme: Theme,
+    low_health_warning: bool,
+    low_health_warning_threshold: f64,
+    target_zoom: Option<f64>,
+    mouse_screen_position: Option<(f64, f64)>,
+    followed_troop: Option<u32>,
+    zoom_focus: (f64, f64),
+    show_scale_bar: bool,
+    secondary_views: Vec<SecondaryView>,
+    drawing_secondary_view: bool,
+    show_stack_counts: bool,
+    previous_orders: Vec<PreviousOrder>,
+    health_bar_mode: HealthBarMode,
+    show_names: bool,
+    // Screen position the radial command menu is open at
+    command_menu: Option<(f64, f64)>,
+    click_radius: f64,
+    // World radius of the area ability being aimed, if any
+    ability_target_radius: Option<f64>,
+    // Friendly troops inside the selection being dragged, shown before it's released
+    tentative_selection_count: Option<usize>,
+    quality: u8,
+    grid_type: GridType,
+    spread_crowds: bool,
+    spawn_templates: HashMap<String, SpawnTemplate>,
+    active_template: Option<String>,
 }
 
 #[wasm_bindgen]
@@ -110,6 +738,15 @@ impl Renderer {
             .unwrap()
             .dyn_into::<CanvasRenderingContext2d>()?;
         
+        // Offscreen canvas used to composite the fog-of-war overlay
+        let fog_canvas = document.create_element("canvas")?
+            .dyn_into::<HtmlCanvasElement>()?;
+        
+        let fog_context = fog_canvas
+            .get_context("2d")?
+            .unwrap()
+            .dyn_into::<CanvasRenderingContext2d>()?;
+        
         Ok(Renderer {
             canvas,
             context,
@@ -126,6 +763,95 @@ impl Renderer {
             selection_start: None,
             selection_end: None,
             selected_troops: Vec::new(),
+            control_groups: HashMap::new(),
+            fog_enabled: false,
+            vision_radius: 200.0,
+            fog_canvas,
+            fog_context,
+            show_minimap: true,
+            selection_callback: None,
+            frame_times: VecDeque::new(),
+            last_frame_time: None,
+            attack_move_modifier: false,
+            show_ranges: true,
+            edge_scroll_enabled: false,
+            edge_scroll_margin: 20.0,
+            iff_enabled: true,
+            paused: false,
+            buffered_state: None,
+            seeking: false,
+            direction_line_length: 10.0,
+            direction_line_color: "#ffffff".to_string(),
+            spawn_unit_type: "basic".to_string(),
+            active_touches: HashMap::new(),
+            touch_moved: false,
+            pinch_distance: None,
+            show_trails: true,
+            snap_to_grid: false,
+            formation_moves: true,
+            mouse_world_position: None,
+            troop_index: TroopIndex::new(50.0),
+            interpolated: None,
+            interpolated_index: TroopIndex::new(50.0),
+            show_paths: true,
+            colorblind_mode: ColorblindMode::None,
+            health_shading: false,
+            waypoints: Vec::new(),
+            waypoint_troops: Vec::new(),
+            ruler_enabled: false,
+            show_grid_labels: false,
+            previous_state: None,
+            previous_state_time: 0.0,
+            game_state_time: 0.0,
+            death_markers: Vec::new(),
+            hovered_troop: None,
+            camera_rotation: 0.0,
+            detection_radius: 100.0,
+            render_stats: Cell::new(RenderStats::default()),
+            min_zoom: 0.2,
+            max_zoom: 5.0,
+            pixel_ratio: 1.0,
+            show_troop_ids: false,
+            camera_animation: None,
+            show_offscreen_indicators: true,
+            impact_markers: Vec::new(),
+            grid_size: 100.0,
+            show_grid: true,
+            snapshots: VecDeque::new(),
+            troop_angles: RefCell::new(HashMap::new()),
+            event_log: VecDeque::new(),
+            selection_mode: SelectionMode::Box,
+            move_confirmation: None,
+            selected_brightness: 0.3,
+            damage_numbers: Vec::new(),
+            render_style: RenderStyle::Filled,
+            rally_point: None,
+            highlighted: Vec::new(),
+            rejected_entities: 0,
+            show_attack_lines: true,
+            theme: Theme::default(),
+            low_health_warning: true,
+            low_health_warning_threshold: 0.25,
+            target_zoom: None,
+            mouse_screen_position: None,
+            followed_troop: None,
+            zoom_focus: (0.0, 0.0),
+            show_scale_bar: true,
+            secondary_views: Vec::new(),
+            drawing_secondary_view: false,
+            show_stack_counts: true,
+            previous_orders: Vec::new(),
+            health_bar_mode: HealthBarMode::Always,
+            show_names: true,
+            command_menu: None,
+            click_radius: 10.0,
+            ability_target_radius: None,
+            tentative_selection_count: None,
+            quality: 2,
+            grid_type: GridType::Square,
+            spread_crowds: true,
+            spawn_templates: HashMap::new(),
+            active_template: None,
         })
     }
     
@@ -136,442 +862,3833 @@ impl Renderer {
     
     #[wasm_bindgen]
     pub fn update_game_state(&mut self, state_js: JsValue) -> Result<(), JsValue> {
-        let game_state: GameState = serde_wasm_bindgen::from_value(state_js)?;
-        self.game_state = Some(game_state);
+        let mut game_state: GameState = serde_wasm_bindgen::from_value(state_js)?;
+        self.rejected_entities += game_state.sanitize();
+        
+        // While paused, hold on to the latest state without displaying it
+        if self.paused {
+            self.buffered_state = Some(game_state);
+        } else {
+            self.set_game_state(game_state);
+        }
         Ok(())
     }
     
     #[wasm_bindgen]
-    pub fn update_dev_data(&mut self, data_js: JsValue) -> Result<(), JsValue> {
-        let dev_data: DevData = serde_wasm_bindgen::from_value(data_js)?;
-        self.dev_data = Some(dev_data);
+    pub fn push_snapshot(&mut self, state_js: JsValue) -> Result<(), JsValue> {
+        let mut game_state: GameState = serde_wasm_bindgen::from_value(state_js)?;
+        self.rejected_entities += game_state.sanitize();
+        self.snapshots.push_back((now(), game_state));
+        
+        // Keep roughly the last minute at 10 updates per second
+        while self.snapshots.len() > 600 {
+            self.snapshots.pop_front();
+        }
         Ok(())
     }
     
     #[wasm_bindgen]
-    pub fn toggle_dev_tools(&mut self) {
-        self.show_dev_tools = !self.show_dev_tools;
+    pub fn get_snapshot_range(&self) -> Option<Array> {
+        // Timestamps of the oldest and newest buffered snapshots, for a replay slider
+        let (first, _) = self.snapshots.front()?;
+        let (last, _) = self.snapshots.back()?;
+        Some(array_from_tuple((*first, *last)))
     }
     
     #[wasm_bindgen]
-    pub fn handle_mouse_down(&mut self, event: MouseEvent) {
-        let rect = self.canvas.get_bounding_client_rect();
-        let x = event.client_x() as f64 - rect.left();
-        let y = event.client_y() as f64 - rect.top();
-        
-        // Convert to world coordinates
-        let world_x = x / self.zoom + self.camera_x;
-        let world_y = y / self.zoom + self.camera_y;
+    pub fn seek(&mut self, timestamp: f64) {
+        let snapshot = match self.snapshots.iter().min_by(|a, b| (a.0 - timestamp).abs().total_cmp(&(b.0 - timestamp).abs())) {
+            Some((_, snapshot)) => snapshot.clone(),
+            None => return,
+        };
         
-        // Left mouse button (0) for selection or camera drag
-        if event.button() == 0 {
-            // Start selection by default, use Alt key for camera movement
-            if event.alt_key() {
-                // Alt + left click for camera movement
-                self.is_dragging = true;
-                self.last_mouse_x = x;
-                self.last_mouse_y = y;
-            } else {
-                // Regular left click for selection
-                self.selection_start = Some((world_x, world_y));
-                self.selection_end = Some((world_x, world_y));
-                self.is_dragging = false;
-                
-                // If clicking outside of any selected troops, clear selection
-                if !self.is_clicking_selected_troop(world_x, world_y) {
-                    self.selected_troops.clear();
-                }
-            }
+        // Seeking implies paused; keep the live state so unpausing returns to it
+        self.paused = true;
+        self.seeking = true;
+        if self.buffered_state.is_none() {
+            self.buffered_state = self.game_state.take();
         }
+        
+        // Show the snapshot as-is, without interpolating or spawning death markers
+        self.previous_state = None;
+        self.troop_index.rebuild(&snapshot.troops);
+        self.game_state = Some(snapshot);
     }
     
-    fn is_clicking_selected_troop(&self, world_x: f64, world_y: f64) -> bool {
-        if let Some(game_state) = &self.game_state {
+    fn set_game_state(&mut self, game_state: GameState) {
+        // Keep the outgoing snapshot so render can interpolate between the two
+        self.previous_state = self.game_state.take();
+        self.previous_state_time = self.game_state_time;
+        self.game_state_time = now();
+        
+        // Troops that vanished since the last snapshot are assumed dead
+        if let Some(previous) = &self.previous_state {
+            // Only mark deaths the player could see, so fog and stealth don't give them away
+            let alive: HashSet<u32> = game_state.troops.iter().map(|t| t.id).collect();
+            for troop in previous.troops.iter().filter(|t| !alive.contains(&t.id)) {
+                if !self.is_troop_visible(previous, troop) {
+                    continue;
+                }
+                self.death_markers.push(DeathMarker {
+                    position: troop.position,
+                    color: troop.color,
+                    created_at: self.game_state_time,
+                });
+            }
+            
+            // Troops that lost health since the last snapshot took damage
+            let previous_health: HashMap<u32, f64> = previous.troops.iter().map(|t| (t.id, t.health)).collect();
             for troop in &game_state.troops {
-                if self.selected_troops.contains(&troop.id) {
-                    let dx = world_x - troop.position.0;
-                    let dy = world_y - troop.position.1;
-                    let distance = (dx * dx + dy * dy).sqrt();
-                    
-                    if distance < 10.0 {  // Selection radius
-                        return true;
+                if let Some(&health) = previous_health.get(&troop.id) {
+                    if troop.health < health && self.is_troop_visible(&game_state, troop) {
+                        self.damage_numbers.push(DamageNumber {
+                            position: troop.position,
+                            amount: health - troop.health,
+                            created_at: self.game_state_time,
+                        });
                     }
                 }
             }
+            
+            // Projectiles that vanished have hit something or run out of range; like the
+            // projectiles themselves, impacts under fog stay hidden
+            let in_flight: HashSet<u32> = game_state.projectiles.iter().map(|p| p.id).collect();
+            for projectile in previous.projectiles.iter().filter(|p| !in_flight.contains(&p.id)) {
+                if !self.is_visible(previous, projectile.player_id, projectile.position) {
+                    continue;
+                }
+                self.impact_markers.push(ImpactMarker {
+                    position: projectile.position,
+                    color: projectile.color,
+                    created_at: self.game_state_time,
+                });
+            }
         }
-        false
+        
+        self.troop_index.rebuild(&game_state.troops);
+        self.game_state = Some(game_state);
     }
     
-    #[wasm_bindgen]
-    pub fn handle_mouse_move(&mut self, event: MouseEvent) {
-        let rect = self.canvas.get_bounding_client_rect();
-        let x = event.client_x() as f64 - rect.left();
-        let y = event.client_y() as f64 - rect.top();
+    fn interpolated_state(&self) -> Option<GameState> {
+        if self.paused {
+            return None;
+        }
         
-        // Convert to world coordinates
-        let world_x = x / self.zoom + self.camera_x;
-        let world_y = y / self.zoom + self.camera_y;
+        let previous = self.previous_state.as_ref()?;
+        let latest = self.game_state.as_ref()?;
         
-        if self.is_dragging {
-            let dx = x - self.last_mouse_x;
-            let dy = y - self.last_mouse_y;
-            
-            // Move camera in the opposite direction of mouse movement
-            self.camera_x -= dx / self.zoom;
-            self.camera_y -= dy / self.zoom;
-            
-            self.last_mouse_x = x;
-            self.last_mouse_y = y;
-        } else if self.selection_start.is_some() {
-            // Update selection end point
-            self.selection_end = Some((world_x, world_y));
+        // Progress from the previous snapshot to the latest one, one update interval behind
+        let interval = self.game_state_time - self.previous_state_time;
+        if interval <= 0.0 {
+            return None;
+        }
+        let t = ((now() - self.game_state_time) / interval).clamp(0.0, 1.0);
+        
+        let (map_width, map_height) = latest.map_size;
+        let lerp = |from: (f64, f64), to: (f64, f64)| {
+            // Snap instead of sliding across the map when an entity wraps around the edge
+            if (to.0 - from.0).abs() > map_width / 2.0 || (to.1 - from.1).abs() > map_height / 2.0 {
+                to
+            } else {
+                (from.0 + (to.0 - from.0) * t, from.1 + (to.1 - from.1) * t)
+            }
+        };
+        
+        let previous_troops: HashMap<u32, (f64, f64)> = previous.troops.iter()
+            .map(|troop| (troop.id, troop.position))
+            .collect();
+        let previous_projectiles: HashMap<u32, (f64, f64)> = previous.projectiles.iter()
+            .map(|projectile| (projectile.id, projectile.position))
+            .collect();
+        
+        // Entities missing from the previous snapshot are drawn at their latest position
+        let mut state = latest.clone();
+        for troop in &mut state.troops {
+            if let Some(&from) = previous_troops.get(&troop.id) {
+                troop.position = lerp(from, troop.position);
+            }
+        }
+        for projectile in &mut state.projectiles {
+            if let Some(&from) = previous_projectiles.get(&projectile.id) {
+                projectile.position = lerp(from, projectile.position);
+            }
         }
+        
+        Some(state)
     }
     
     #[wasm_bindgen]
-    pub fn handle_mouse_up(&mut self, event: MouseEvent) {
-        // If we were making a selection, finalize it
-        if self.selection_start.is_some() && self.selection_end.is_some() {
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+        
+        // Catch up to the most recent state on unpause
+        if !paused && self.seeking {
+            // The replay snapshot has nothing to do with the live state, so neither diff
+            // against it nor interpolate from it
+            self.seeking = false;
+            self.previous_state = None;
+            self.game_state_time = now();
+            self.game_state = self.buffered_state.take();
+            let troops = self.game_state.as_ref().map_or(&[][..], |state| &state.troops[..]);
+            self.troop_index.rebuild(troops);
+        } else if !paused {
+            if let Some(game_state) = self.buffered_state.take() {
+                self.set_game_state(game_state);
+            }
+        }
+    }
+    
+    #[wasm_bindgen]
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+    
+    #[wasm_bindgen]
+    pub fn capture_frame(&self) -> Result<ImageData, JsValue> {
+        // Read back the last rendered frame; pause first for a frame that stays still between calls
+        if self.last_frame_time.is_none() || self.game_state.is_none() {
+            return Err(JsValue::from_str("No frame has been rendered yet"));
+        }
+        
+        self.context.get_image_data(0.0, 0.0, self.canvas.width() as f64, self.canvas.height() as f64)
+    }
+    
+    #[wasm_bindgen]
+    pub fn update_dev_data(&mut self, data_js: JsValue) -> Result<(), JsValue> {
+        let dev_data: DevData = serde_wasm_bindgen::from_value(data_js)?;
+        self.dev_data = Some(dev_data);
+        Ok(())
+    }
+    
+    #[wasm_bindgen]
+    pub fn export_settings(&self) -> Result<JsValue, JsValue> {
+        let settings = RendererSettings {
+            camera_x: Some(self.camera_x),
+            camera_y: Some(self.camera_y),
+            zoom: Some(self.zoom),
+            show_dev_tools: Some(self.show_dev_tools),
+            fog_enabled: Some(self.fog_enabled),
+            vision_radius: Some(self.vision_radius),
+            show_minimap: Some(self.show_minimap),
+            show_ranges: Some(self.show_ranges),
+            edge_scroll_enabled: Some(self.edge_scroll_enabled),
+            edge_scroll_margin: Some(self.edge_scroll_margin),
+            iff_enabled: Some(self.iff_enabled),
+            direction_line_length: Some(self.direction_line_length),
+            direction_line_color: Some(self.direction_line_color.clone()),
+            show_trails: Some(self.show_trails),
+            snap_to_grid: Some(self.snap_to_grid),
+            formation_moves: Some(self.formation_moves),
+            show_paths: Some(self.show_paths),
+            health_shading: Some(self.health_shading),
+            show_grid_labels: Some(self.show_grid_labels),
+            camera_rotation: Some(self.camera_rotation),
+            detection_radius: Some(self.detection_radius),
+            show_troop_ids: Some(self.show_troop_ids),
+            show_offscreen_indicators: Some(self.show_offscreen_indicators),
+            show_grid: Some(self.show_grid),
+            grid_size: Some(self.grid_size),
+            selected_brightness: Some(self.selected_brightness),
+            show_attack_lines: Some(self.show_attack_lines),
+            low_health_warning: Some(self.low_health_warning),
+            low_health_warning_threshold: Some(self.low_health_warning_threshold),
+            show_scale_bar: Some(self.show_scale_bar),
+            show_stack_counts: Some(self.show_stack_counts),
+            show_names: Some(self.show_names),
+            click_radius: Some(self.click_radius),
+            quality: Some(self.quality),
+            spread_crowds: Some(self.spread_crowds),
+            colorblind_mode: Some(self.colorblind_mode.name().to_string()),
+            selection_mode: Some(self.selection_mode.name().to_string()),
+            render_style: Some(self.render_style.name().to_string()),
+            health_bar_mode: Some(self.health_bar_mode.name().to_string()),
+            grid_type: Some(self.grid_type.name().to_string()),
+        };
+        Ok(serde_wasm_bindgen::to_value(&settings)?)
+    }
+    
+    #[wasm_bindgen]
+    pub fn import_settings(&mut self, settings_js: JsValue) -> Result<(), JsValue> {
+        let settings: RendererSettings = serde_wasm_bindgen::from_value(settings_js)?;
+        
+        // Check every named option up front, so a bad one doesn't leave the import half-applied
+        let colorblind_mode = parse_name(&settings.colorblind_mode, ColorblindMode::from_name, "colorblind mode")?;
+        let selection_mode = parse_name(&settings.selection_mode, SelectionMode::from_name, "selection mode")?;
+        let render_style = parse_name(&settings.render_style, RenderStyle::from_name, "render style")?;
+        let health_bar_mode = parse_name(&settings.health_bar_mode, HealthBarMode::from_name, "health bar mode")?;
+        let grid_type = parse_name(&settings.grid_type, GridType::from_name, "grid type")?;
+        
+        if let Some(camera_x) = settings.camera_x {
+            self.camera_x = camera_x;
+        }
+        if let Some(camera_y) = settings.camera_y {
+            self.camera_y = camera_y;
+        }
+        if let Some(zoom) = settings.zoom {
+            self.zoom = zoom;
+        }
+        if let Some(show_dev_tools) = settings.show_dev_tools {
+            self.show_dev_tools = show_dev_tools;
+        }
+        if let Some(fog_enabled) = settings.fog_enabled {
+            self.fog_enabled = fog_enabled;
+        }
+        if let Some(vision_radius) = settings.vision_radius {
+            self.set_vision_radius(vision_radius);
+        }
+        if let Some(show_minimap) = settings.show_minimap {
+            self.show_minimap = show_minimap;
+        }
+        if let Some(show_ranges) = settings.show_ranges {
+            self.show_ranges = show_ranges;
+        }
+        if let Some(edge_scroll_enabled) = settings.edge_scroll_enabled {
+            self.edge_scroll_enabled = edge_scroll_enabled;
+        }
+        if let Some(edge_scroll_margin) = settings.edge_scroll_margin {
+            self.set_edge_scroll_margin(edge_scroll_margin);
+        }
+        if let Some(iff_enabled) = settings.iff_enabled {
+            self.iff_enabled = iff_enabled;
+        }
+        if settings.direction_line_length.is_some() || settings.direction_line_color.is_some() {
+            let length = settings.direction_line_length.unwrap_or(self.direction_line_length);
+            let color = settings.direction_line_color.unwrap_or_else(|| self.direction_line_color.clone());
+            self.set_direction_line_style(length, color);
+        }
+        if let Some(show_trails) = settings.show_trails {
+            self.show_trails = show_trails;
+        }
+        if let Some(snap_to_grid) = settings.snap_to_grid {
+            self.snap_to_grid = snap_to_grid;
+        }
+        if let Some(formation_moves) = settings.formation_moves {
+            self.formation_moves = formation_moves;
+        }
+        if let Some(show_paths) = settings.show_paths {
+            self.show_paths = show_paths;
+        }
+        if let Some(health_shading) = settings.health_shading {
+            self.health_shading = health_shading;
+        }
+        if let Some(show_grid_labels) = settings.show_grid_labels {
+            self.show_grid_labels = show_grid_labels;
+        }
+        if let Some(camera_rotation) = settings.camera_rotation {
+            self.camera_rotation = camera_rotation.rem_euclid(2.0 * PI);
+        }
+        if let Some(detection_radius) = settings.detection_radius {
+            self.set_detection_radius(detection_radius);
+        }
+        if let Some(show_troop_ids) = settings.show_troop_ids {
+            self.show_troop_ids = show_troop_ids;
+        }
+        if let Some(show_offscreen_indicators) = settings.show_offscreen_indicators {
+            self.show_offscreen_indicators = show_offscreen_indicators;
+        }
+        if let Some(show_grid) = settings.show_grid {
+            self.show_grid = show_grid;
+        }
+        if let Some(grid_size) = settings.grid_size {
+            self.set_grid_size(grid_size);
+        }
+        if let Some(selected_brightness) = settings.selected_brightness {
+            self.set_selected_brightness(selected_brightness);
+        }
+        if let Some(show_attack_lines) = settings.show_attack_lines {
+            self.show_attack_lines = show_attack_lines;
+        }
+        if let Some(low_health_warning) = settings.low_health_warning {
+            self.low_health_warning = low_health_warning;
+        }
+        if let Some(low_health_warning_threshold) = settings.low_health_warning_threshold {
+            self.set_low_health_warning_threshold(low_health_warning_threshold);
+        }
+        if let Some(show_scale_bar) = settings.show_scale_bar {
+            self.show_scale_bar = show_scale_bar;
+        }
+        if let Some(show_stack_counts) = settings.show_stack_counts {
+            self.show_stack_counts = show_stack_counts;
+        }
+        if let Some(show_names) = settings.show_names {
+            self.show_names = show_names;
+        }
+        if let Some(click_radius) = settings.click_radius {
+            self.set_click_radius(click_radius);
+        }
+        if let Some(quality) = settings.quality {
+            self.set_quality(quality);
+        }
+        if let Some(spread_crowds) = settings.spread_crowds {
+            self.spread_crowds = spread_crowds;
+        }
+        if let Some(mode) = colorblind_mode {
+            self.colorblind_mode = mode;
+        }
+        if let Some(mode) = selection_mode {
+            self.selection_mode = mode;
+        }
+        if let Some(style) = render_style {
+            self.render_style = style;
+        }
+        if let Some(mode) = health_bar_mode {
+            self.health_bar_mode = mode;
+        }
+        if let Some(grid_type) = grid_type {
+            self.grid_type = grid_type;
+        }
+        
+        // Restored values may be out of range for the current map or canvas
+        self.zoom = self.zoom.clamp(self.min_zoom, self.max_zoom);
+        self.clamp_camera();
+        Ok(())
+    }
+    
+    #[wasm_bindgen]
+    pub fn toggle_dev_tools(&mut self) {
+        self.show_dev_tools = !self.show_dev_tools;
+    }
+    
+    #[wasm_bindgen]
+    pub fn toggle_troop_ids(&mut self) {
+        // Only takes effect while dev tools are shown
+        self.show_troop_ids = !self.show_troop_ids;
+    }
+    
+    #[wasm_bindgen]
+    pub fn toggle_fog(&mut self) {
+        self.fog_enabled = !self.fog_enabled;
+    }
+    
+    #[wasm_bindgen]
+    pub fn set_vision_radius(&mut self, radius: f64) {
+        self.vision_radius = radius.max(0.0);
+    }
+    
+    #[wasm_bindgen]
+    pub fn set_selected_brightness(&mut self, factor: f64) {
+        // 0 disables brightening, 1 draws selected troops white
+        self.selected_brightness = factor.clamp(0.0, 1.0);
+    }
+    
+    #[wasm_bindgen]
+    pub fn set_detection_radius(&mut self, radius: f64) {
+        self.detection_radius = radius.max(0.0);
+    }
+    
+    #[wasm_bindgen]
+    pub fn set_direction_line_style(&mut self, length: f64, color: String) {
+        self.direction_line_length = length.max(0.0);
+        self.direction_line_color = color;
+    }
+    
+    #[wasm_bindgen]
+    pub fn set_colorblind_mode(&mut self, mode: String) -> Result<(), JsValue> {
+        self.colorblind_mode = ColorblindMode::from_name(&mode)
+            .ok_or_else(|| JsValue::from_str(&format!("Unknown colorblind mode: {}", mode)))?;
+        Ok(())
+    }
+    
+    #[wasm_bindgen]
+    pub fn set_selection_mode(&mut self, mode: String) -> Result<(), JsValue> {
+        // "box" drags a rectangle, "circle" drags a radius out from the start point
+        self.selection_mode = SelectionMode::from_name(&mode)
+            .ok_or_else(|| JsValue::from_str(&format!("Unknown selection mode: {}", mode)))?;
+        Ok(())
+    }
+    
+    #[wasm_bindgen]
+    pub fn set_render_style(&mut self, style: String) -> Result<(), JsValue> {
+        // "filled" or "outlined" troop shapes; rings and health bars are unaffected
+        self.render_style = RenderStyle::from_name(&style)
+            .ok_or_else(|| JsValue::from_str(&format!("Unknown render style: {}", style)))?;
+        Ok(())
+    }
+    
+    #[wasm_bindgen]
+    pub fn set_health_bar_mode(&mut self, mode: String) -> Result<(), JsValue> {
+        // "always", "damaged_or_selected" or "never"
+        self.health_bar_mode = HealthBarMode::from_name(&mode)
+            .ok_or_else(|| JsValue::from_str(&format!("Unknown health bar mode: {}", mode)))?;
+        Ok(())
+    }
+    
+    #[wasm_bindgen]
+    pub fn set_grid_type(&mut self, grid_type: String) -> Result<(), JsValue> {
+        // "square" or "hex"; hexes are grid_size wide, and snapping follows the grid type
+        self.grid_type = GridType::from_name(&grid_type)
+            .ok_or_else(|| JsValue::from_str(&format!("Unknown grid type: {}", grid_type)))?;
+        Ok(())
+    }
+    
+    fn apply_colorblind(&self, rgb: (u8, u8, u8)) -> (u8, u8, u8) {
+        // Simulation matrix for how the color is perceived with each deficiency
+        let simulation = match self.colorblind_mode {
+            ColorblindMode::None => return rgb,
+            ColorblindMode::Protanopia => [[0.567, 0.433, 0.0], [0.558, 0.442, 0.0], [0.0, 0.242, 0.758]],
+            ColorblindMode::Deuteranopia => [[0.625, 0.375, 0.0], [0.7, 0.3, 0.0], [0.0, 0.3, 0.7]],
+            ColorblindMode::Tritanopia => [[0.95, 0.05, 0.0], [0.0, 0.433, 0.567], [0.0, 0.475, 0.525]],
+        };
+        
+        // Daltonize: shift the color information that would be lost into channels that remain visible
+        let color = [rgb.0 as f64, rgb.1 as f64, rgb.2 as f64];
+        let simulated = simulation.map(|row| row[0] * color[0] + row[1] * color[1] + row[2] * color[2]);
+        let error = [color[0] - simulated[0], color[1] - simulated[1], color[2] - simulated[2]];
+        
+        let corrected = [
+            color[0],
+            color[1] + 0.7 * error[0] + error[1],
+            color[2] + 0.7 * error[0] + error[2],
+        ];
+        let to_channel = |value: f64| value.round().clamp(0.0, 255.0) as u8;
+        
+        (to_channel(corrected[0]), to_channel(corrected[1]), to_channel(corrected[2]))
+    }
+    
+    #[wasm_bindgen]
+    pub fn toggle_health_shading(&mut self) {
+        self.health_shading = !self.health_shading;
+    }
+    
+    #[wasm_bindgen]
+    pub fn toggle_iff(&mut self) {
+        self.iff_enabled = !self.iff_enabled;
+    }
+    
+    #[wasm_bindgen]
+    pub fn toggle_trails(&mut self) {
+        self.show_trails = !self.show_trails;
+    }
+    
+    #[wasm_bindgen]
+    pub fn toggle_paths(&mut self) {
+        self.show_paths = !self.show_paths;
+    }
+    
+    #[wasm_bindgen]
+    pub fn toggle_ranges(&mut self) {
+        self.show_ranges = !self.show_ranges;
+    }
+    
+    #[wasm_bindgen]
+    pub fn toggle_ruler(&mut self) {
+        self.ruler_enabled = !self.ruler_enabled;
+    }
+    
+    #[wasm_bindgen]
+    pub fn toggle_offscreen_indicators(&mut self) {
+        self.show_offscreen_indicators = !self.show_offscreen_indicators;
+    }
+    
+    #[wasm_bindgen]
+    pub fn toggle_scale_bar(&mut self) {
+        self.show_scale_bar = !self.show_scale_bar;
+    }
+    
+    #[wasm_bindgen]
+    pub fn toggle_stack_counts(&mut self) {
+        self.show_stack_counts = !self.show_stack_counts;
+    }
+    
+    #[wasm_bindgen]
+    pub fn toggle_names(&mut self) {
+        self.show_names = !self.show_names;
+    }
+    
+    #[wasm_bindgen]
+    pub fn toggle_spread_crowds(&mut self) {
+        self.spread_crowds = !self.spread_crowds;
+    }
+    
+    #[wasm_bindgen]
+    pub fn toggle_grid(&mut self) {
+        self.show_grid = !self.show_grid;
+    }
+    
+    #[wasm_bindgen]
+    pub fn set_grid_size(&mut self, size: f64) {
+        // Ignore sizes that would never advance the grid loops, or would draw
+        // millions of lines when zoomed out
+        if size >= MIN_GRID_SIZE && size.is_finite() {
+            self.grid_size = size;
+        }
+    }
+    
+    #[wasm_bindgen]
+    pub fn toggle_attack_lines(&mut self) {
+        self.show_attack_lines = !self.show_attack_lines;
+    }
+    
+    #[wasm_bindgen]
+    pub fn toggle_low_health_warning(&mut self) {
+        self.low_health_warning = !self.low_health_warning;
+    }
+    
+    #[wasm_bindgen]
+    pub fn set_low_health_warning_threshold(&mut self, fraction: f64) {
+        // Fraction of max health below which friendly troops pulse red
+        self.low_health_warning_threshold = fraction.clamp(0.0, 1.0);
+    }
+    
+    #[wasm_bindgen]
+    pub fn toggle_grid_labels(&mut self) {
+        self.show_grid_labels = !self.show_grid_labels;
+    }
+    
+    #[wasm_bindgen]
+    pub fn toggle_minimap(&mut self) {
+        self.show_minimap = !self.show_minimap;
+    }
+    
+    #[wasm_bindgen]
+    pub fn handle_minimap_click(&mut self, canvas_x: f64, canvas_y: f64) -> bool {
+        if !self.show_minimap {
+            return false;
+        }
+        
+        let map_size = match &self.game_state {
+            Some(game_state) => game_state.map_size,
+            None => return false,
+        };
+        
+        let (origin_x, origin_y, scale) = self.minimap_layout(map_size);
+        let world_x = (canvas_x - origin_x) / scale;
+        let world_y = (canvas_y - origin_y) / scale;
+        
+        // Ignore clicks outside the minimap
+        if world_x < 0.0 || world_x > map_size.0 || world_y < 0.0 || world_y > map_size.1 {
+            return false;
+        }
+        
+        // Center the camera on the clicked world position
+        self.animate_camera_to(world_x, world_y, self.zoom);
+        
+        true
+    }
+    
+    #[wasm_bindgen]
+    pub fn handle_mouse_down(&mut self, event: MouseEvent) {
+        let (x, y) = self.mouse_position(&event);
+        
+        // Convert to world coordinates
+        let (world_x, world_y) = self.screen_to_world(x, y);
+        
+        // A left click while aiming an ability cancels it and leaves the selection alone
+        if event.button() == 0 && self.ability_target_radius.is_some() {
+            self.cancel_ability_target();
+            return;
+        }
+        
+        // Left mouse button (0) for selection or camera drag
+        if event.button() == 0 {
+            // Start selection by default, use Alt key for camera movement
+            if event.alt_key() {
+                // Alt + left click for camera movement
+                self.is_dragging = true;
+                self.last_mouse_x = x;
+                self.last_mouse_y = y;
+            } else {
+                // Regular left click for selection
+                self.selection_start = Some((x, y));
+                self.selection_end = Some((x, y));
+                self.is_dragging = false;
+                
+                // If clicking outside of any selected troops, clear selection (measuring and Shift keep it)
+                if !self.ruler_enabled && !event.shift_key() && !self.is_clicking_selected_troop(world_x, world_y) {
+                    self.set_selection(Vec::new());
+                }
+            }
+        }
+    }
+    
+    #[wasm_bindgen]
+    pub fn handle_inspect_click(&self, event: MouseEvent) -> Option<JsValue> {
+        let game_state = self.game_state.as_ref()?;
+        let player_id = self.player_id?;
+        
+        let (x, y) = self.mouse_position(&event);
+        
+        // Convert to world coordinates
+        let (world_x, world_y) = self.screen_to_world(x, y);
+        
+        // Only visible enemy troops can be inspected; selection is left untouched
+        let troop = self.nearest_troop_at(world_x, world_y, |t| {
+            t.player_id != player_id && self.is_troop_visible(game_state, t)
+        })?;
+        
+        serde_wasm_bindgen::to_value(&TroopStats::from_troop(troop)).ok()
+    }
+    
+    #[wasm_bindgen]
+    pub fn troop_at_screen(&self, canvas_x: f64, canvas_y: f64) -> Option<JsValue> {
+        let game_state = self.game_state.as_ref()?;
+        
+        // Convert to world coordinates
+        let (world_x, world_y) = self.screen_to_world(canvas_x, canvas_y);
+        
+        let troop = self.nearest_troop_at(world_x, world_y, |t| {
+            self.is_troop_visible(game_state, t)
+        })?;
+        
+        serde_wasm_bindgen::to_value(troop).ok()
+    }
+    
+    #[wasm_bindgen]
+    pub fn troops_in_rect(&self, min_x: f64, min_y: f64, max_x: f64, max_y: f64, owner: Option<u32>) -> JsValue {
+        // Visible troops inside a world-space box (edges included), optionally only those of one player
+        let troops: Vec<&Troop> = match &self.game_state {
+            Some(game_state) => troops_in_box(&game_state.troops, (min_x, min_y), (max_x, max_y), owner)
+                .into_iter()
+                .filter(|t| self.is_troop_visible(game_state, t))
+                .collect(),
+            None => Vec::new(),
+        };
+        
+        serde_wasm_bindgen::to_value(&troops).unwrap_or(JsValue::NULL)
+    }
+    
+    fn is_clicking_selected_troop(&self, world_x: f64, world_y: f64) -> bool {
+        self.troops_near(world_x, world_y).iter()
+            .any(|troop| self.selected_troops.contains(&troop.id) && self.is_point_on_troop(troop, world_x, world_y))
+    }
+    
+    fn hit_radius(&self) -> f64 {
+        // Selection radius, constant on screen so clicks feel the same at any zoom
+        self.click_radius / self.zoom
+    }
+    
+    #[wasm_bindgen]
+    pub fn set_quality(&mut self, level: u8) {
+        // 0 = low draws troops as dots without bars, facing lines, trails or fine grid lines,
+        // 1 = medium only drops trails, 2 = high draws everything
+        self.quality = level.min(2);
+    }
+    
+    fn draws_trails(&self) -> bool {
+        self.show_trails && self.quality >= 2
+    }
+    
+    #[wasm_bindgen]
+    pub fn set_click_radius(&mut self, pixels: f64) {
+        if pixels > 0.0 && pixels.is_finite() {
+            self.click_radius = pixels;
+        }
+    }
+    
+    fn is_point_on_troop(&self, troop: &Troop, world_x: f64, world_y: f64) -> bool {
+        let dx = world_x - troop.position.0;
+        let dy = world_y - troop.position.1;
+        let distance = (dx * dx + dy * dy).sqrt();
+        
+        // Clicks anywhere on the drawn shape count, even when that's bigger than the radius
+        distance < self.hit_radius().max(troop.size() / 2.0)
+    }
+    
+    fn troops_near(&self, world_x: f64, world_y: f64) -> Vec<&Troop> {
+        // Candidate troops for hit-testing, looked up through the spatial index
+        match &self.game_state {
+            Some(game_state) => self.troop_index.query(world_x, world_y, self.hit_radius().max(self.troop_index.max_troop_size / 2.0))
+                .into_iter()
+                .filter_map(|i| game_state.troops.get(i))
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+    
+    fn camera(&self) -> Camera {
+        Camera {
+            x: self.camera_x,
+            y: self.camera_y,
+            zoom: self.zoom,
+            rotation: self.camera_rotation,
+            view_width: self.view_width(),
+            view_height: self.view_height(),
+        }
+    }
+    
+    fn visible_world_rect(&self) -> (f64, f64, f64, f64) {
+        self.camera().visible_world_rect()
+    }
+    
+    fn visible_world_corners(&self) -> [(f64, f64); 4] {
+        self.camera().visible_world_corners()
+    }
+    
+    // Conversions for positioning DOM overlays over the canvas, as [x, y] arrays
+    #[wasm_bindgen(js_name = screen_to_world)]
+    pub fn screen_to_world_js(&self, x: f64, y: f64) -> Array {
+        array_from_tuple(self.screen_to_world(x, y))
+    }
+    
+    #[wasm_bindgen(js_name = world_to_screen)]
+    pub fn world_to_screen_js(&self, x: f64, y: f64) -> Array {
+        array_from_tuple(self.world_to_screen(x, y))
+    }
+    
+    fn screen_to_world(&self, x: f64, y: f64) -> (f64, f64) {
+        self.camera().screen_to_world(x, y)
+    }
+    
+    fn world_to_screen(&self, x: f64, y: f64) -> (f64, f64) {
+        self.camera().world_to_screen(x, y)
+    }
+    
+    fn apply_camera_transform(&self, context: &CanvasRenderingContext2d) {
+        // Rotate around the canvas center, then pan and zoom into world space
+        let center_x = self.view_width() / 2.0;
+        let center_y = self.view_height() / 2.0;
+        context.translate(center_x, center_y).unwrap();
+        context.rotate(self.camera_rotation).unwrap();
+        context.translate(-center_x, -center_y).unwrap();
+        context.translate(-self.camera_x * self.zoom, -self.camera_y * self.zoom).unwrap();
+        context.scale(self.zoom, self.zoom).unwrap();
+    }
+    
+    
+    #[wasm_bindgen]
+    pub fn handle_double_click(&mut self, event: MouseEvent) {
+        let (game_state, player_id) = match (&self.game_state, self.player_id) {
+            (Some(game_state), Some(player_id)) => (game_state, player_id),
+            _ => return,
+        };
+        
+        let (x, y) = self.mouse_position(&event);
+        
+        // Convert to world coordinates
+        let (world_x, world_y) = self.screen_to_world(x, y);
+        
+        // Find the friendly troop under the cursor
+        let unit_type = match self.friendly_troop_at(world_x, world_y) {
+            Some(troop) => troop.unit_type.clone(),
+            None => return,
+        };
+        
+        // Select every on-screen friendly troop of the same type
+        let camera = self.camera();
+        let selection = game_state.troops.iter()
+            .filter(|t| t.player_id == player_id && t.unit_type == unit_type)
+            .filter(|t| camera.is_on_screen(t.position.0, t.position.1))
+            .map(|t| t.id)
+            .collect();
+        
+        self.set_selection(selection);
+    }
+    
+    #[wasm_bindgen]
+    pub fn handle_mouse_move(&mut self, event: MouseEvent) {
+        let (x, y) = self.mouse_position(&event);
+        
+        // Convert to world coordinates
+        let (world_x, world_y) = self.screen_to_world(x, y);
+        self.mouse_world_position = Some((world_x, world_y));
+        self.mouse_screen_position = Some((x, y));
+        
+        // Track the troop under the cursor for the tooltip
+        self.hovered_troop = match &self.game_state {
+            Some(game_state) => self.nearest_troop_at(world_x, world_y, |t| {
+                self.is_troop_visible(game_state, t)
+            }).map(|t| t.id),
+            None => None,
+        };
+        
+        if self.is_dragging {
+            let dx = x - self.last_mouse_x;
+            let dy = y - self.last_mouse_y;
+            
+            // Move camera in the opposite direction of mouse movement
+            self.pan_camera(-dx, -dy);
+            
+            self.last_mouse_x = x;
+            self.last_mouse_y = y;
+        } else if self.selection_start.is_some() {
+            // Update selection end point
+            self.selection_end = Some((x, y));
+            self.update_tentative_selection_count();
+        }
+    }
+    
+    #[wasm_bindgen]
+    pub fn handle_mouse_up(&mut self, event: MouseEvent) {
+        // A ruler drag only measures, so there is no selection to finalize
+        if self.ruler_enabled {
+            self.selection_start = None;
+            self.selection_end = None;
+        }
+        
+        // If we were making a selection, finalize it
+        if self.selection_start.is_some() && self.selection_end.is_some() {
             let start = self.selection_start.unwrap();
             let end = self.selection_end.unwrap();
+            let mode = self.selection_mode;
+            
+            // Only drag-select if the area is large enough (to avoid accidental selections)
+            let (world_x, world_y) = self.screen_to_world(end.0, end.1);
+            if mode.area(start, end) > 25.0 {  // Minimum selection area in screen pixels
+                self.select_troops_on_screen(event.shift_key(), |x, y| mode.contains(start, end, (x, y)));
+            } else if let Some(troop_id) = self.friendly_troop_at(world_x, world_y).map(|t| t.id) {
+                // Treat a tiny box as a click on a single troop, or toggle it in the selection with Shift
+                if event.shift_key() {
+                    self.toggle_troop_selection(troop_id);
+                } else {
+                    self.set_selection(vec![troop_id]);
+                }
+            }
+            
+            self.selection_start = None;
+            self.selection_end = None;
+        }
+        
+        self.tentative_selection_count = None;
+        self.is_dragging = false;
+    }
+    
+    fn update_tentative_selection_count(&mut self) {
+        self.tentative_selection_count = None;
+        
+        let (start, end) = match (self.selection_start, self.selection_end) {
+            (Some(start), Some(end)) if !self.ruler_enabled => (start, end),
+            _ => return,
+        };
+        let (game_state, player_id) = match (&self.game_state, self.player_id) {
+            (Some(game_state), Some(player_id)) => (game_state, player_id),
+            _ => return,
+        };
+        let mode = self.selection_mode;
+        if mode.area(start, end) <= 25.0 {
+            return;
+        }
+        
+        // Only count here; the selection itself is built on mouse up
+        let camera = self.camera();
+        let count = game_state.troops.iter()
+            .filter(|t| t.player_id == player_id)
+            .filter(|t| mode.contains(start, end, camera.world_to_screen(t.position.0, t.position.1)))
+            .count();
+        self.tentative_selection_count = Some(count);
+    }
+    
+    fn friendly_troop_at(&self, world_x: f64, world_y: f64) -> Option<&Troop> {
+        let player_id = self.player_id?;
+        self.nearest_troop_at(world_x, world_y, |t| t.player_id == player_id)
+    }
+    
+    fn nearest_troop_at(&self, world_x: f64, world_y: f64, filter: impl Fn(&Troop) -> bool) -> Option<&Troop> {
+        // Pick the closest matching troop under the point
+        self.troops_near(world_x, world_y).into_iter()
+            .filter(|t| filter(t) && self.is_point_on_troop(t, world_x, world_y))
+            .min_by(|a, b| {
+                let distance_a = (a.position.0 - world_x).powi(2) + (a.position.1 - world_y).powi(2);
+                let distance_b = (b.position.0 - world_x).powi(2) + (b.position.1 - world_y).powi(2);
+                distance_a.total_cmp(&distance_b)
+            })
+    }
+    
+    fn select_troops_on_screen(&mut self, additive: bool, inside: impl Fn(f64, f64) -> bool) {
+        if let Some(game_state) = &self.game_state {
+            if let Some(player_id) = self.player_id {
+                // Select all player's troops inside the dragged screen-space shape, replacing the previous
+                // selection unless adding to it. Troops are tested on screen so this matches what was drawn.
+                let camera = self.camera();
+                let mut selection = if additive { self.selected_troops.clone() } else { Vec::new() };
+                for troop in &game_state.troops {
+                    if troop.player_id == player_id && !selection.contains(&troop.id) {
+                        let (x, y) = camera.world_to_screen(troop.position.0, troop.position.1);
+                        if inside(x, y) {
+                            selection.push(troop.id);
+                        }
+                    }
+                }
+                
+                self.set_selection(selection);
+            }
+        }
+    }
+    
+    fn toggle_troop_selection(&mut self, troop_id: u32) {
+        let selection = toggled_selection(&self.selected_troops, troop_id);
+        self.set_selection(selection);
+    }
+    
+    fn set_selection(&mut self, troop_ids: Vec<u32>) {
+        let changed = !same_troop_set(&self.selected_troops, &troop_ids);
+        self.selected_troops = troop_ids;
+        
+        // Only notify when the set of selected troops actually changed
+        if changed {
+            if let Some(callback) = &self.selection_callback {
+                let _ = callback.call1(&JsValue::NULL, &self.get_selected_troops());
+            }
+        }
+    }
+    
+    #[wasm_bindgen]
+    pub fn clear_selection(&mut self) {
+        // Also drop any drag in progress so a stale box isn't finalized later
+        self.selection_start = None;
+        self.selection_end = None;
+        self.set_selection(Vec::new());
+    }
+    
+    #[wasm_bindgen]
+    pub fn set_highlighted(&mut self, ids: JsValue) -> Result<(), JsValue> {
+        // Pulse these troops independently of the selection, e.g. for tutorials
+        self.highlighted = serde_wasm_bindgen::from_value(ids)?;
+        Ok(())
+    }
+    
+    #[wasm_bindgen]
+    pub fn clear_highlighted(&mut self) {
+        self.highlighted.clear();
+    }
+    
+    #[wasm_bindgen]
+    pub fn select_all(&mut self) {
+        let (game_state, player_id) = match (&self.game_state, self.player_id) {
+            (Some(game_state), Some(player_id)) => (game_state, player_id),
+            _ => return,
+        };
+        
+        // Select every friendly troop on the map, regardless of viewport
+        let selection = game_state.troops.iter()
+            .filter(|t| t.player_id == player_id)
+            .map(|t| t.id)
+            .collect();
+        
+        self.set_selection(selection);
+    }
+    
+    #[wasm_bindgen]
+    pub fn set_selection_callback(&mut self, callback: Function) {
+        self.selection_callback = Some(callback);
+    }
+    
+    #[wasm_bindgen]
+    pub fn assign_control_group(&mut self, group: u8) {
+        // Store the current selection under the given group key (Ctrl+1..9)
+        self.control_groups.insert(group, self.selected_troops.clone());
+    }
+    
+    #[wasm_bindgen]
+    pub fn recall_control_group(&mut self, group: u8) {
+        // Leave the selection untouched if the group was never assigned
+        let stored = match self.control_groups.get(&group) {
+            Some(stored) => stored,
+            None => return,
+        };
+        
+        // Drop any troops that no longer exist
+        let alive: Vec<u32> = match &self.game_state {
+            Some(game_state) => stored.iter()
+                .copied()
+                .filter(|id| game_state.troops.iter().any(|t| t.id == *id))
+                .collect(),
+            None => stored.clone(),
+        };
+        
+        self.set_selection(alive);
+    }
+    
+    fn mouse_position(&self, event: &MouseEvent) -> (f64, f64) {
+        // Event position relative to the canvas, in CSS pixels
+        let rect = self.canvas.get_bounding_client_rect();
+        (event.client_x() as f64 - rect.left(), event.client_y() as f64 - rect.top())
+    }
+    
+    fn touch_position(&self, touch: &Touch) -> (f64, f64) {
+        let rect = self.canvas.get_bounding_client_rect();
+        (touch.client_x() as f64 - rect.left(), touch.client_y() as f64 - rect.top())
+    }
+    
+    fn current_pinch_distance(&self) -> Option<f64> {
+        if self.active_touches.len() != 2 {
+            return None;
+        }
+        
+        let mut positions = self.active_touches.values();
+        let (x1, y1) = positions.next()?;
+        let (x2, y2) = positions.next()?;
+        Some(((x2 - x1).powi(2) + (y2 - y1).powi(2)).sqrt())
+    }
+    
+    #[wasm_bindgen]
+    pub fn handle_touch_start(&mut self, event: TouchEvent) {
+        let touches = event.changed_touches();
+        for i in 0..touches.length() {
+            if let Some(touch) = touches.get(i) {
+                let position = self.touch_position(&touch);
+                self.active_touches.insert(touch.identifier(), position);
+            }
+        }
+        
+        // Only a lone finger can turn out to be a tap
+        self.touch_moved = self.active_touches.len() != 1;
+        
+        self.pinch_distance = self.current_pinch_distance();
+    }
+    
+    #[wasm_bindgen]
+    pub fn handle_touch_move(&mut self, event: TouchEvent) {
+        let touches = event.changed_touches();
+        for i in 0..touches.length() {
+            if let Some(touch) = touches.get(i) {
+                let id = touch.identifier();
+                let (x, y) = self.touch_position(&touch);
+                
+                let (last_x, last_y) = match self.active_touches.get(&id) {
+                    Some(&position) => position,
+                    None => continue,
+                };
+                let dx = x - last_x;
+                let dy = y - last_y;
+                
+                // Ignore small jitter so taps still register
+                if !self.touch_moved && dx.abs() < 5.0 && dy.abs() < 5.0 {
+                    continue;
+                }
+                
+                self.touch_moved = true;
+                self.active_touches.insert(id, (x, y));
+                
+                // Single-finger drag pans the camera
+                if self.active_touches.len() == 1 {
+                    self.pan_camera(-dx, -dy);
+                }
+            }
+        }
+        
+        // Two-finger pinch zooms the camera
+        if let (Some(previous), Some(current)) = (self.pinch_distance, self.current_pinch_distance()) {
+            if previous > 0.0 {
+                self.camera_animation = None;
+                self.target_zoom = None;
+                self.followed_troop = None;
+                self.zoom *= current / previous;
+                self.zoom = self.zoom.clamp(self.min_zoom, self.max_zoom);
+                self.clamp_camera();
+            }
+            self.pinch_distance = Some(current);
+        }
+    }
+    
+    #[wasm_bindgen]
+    pub fn handle_touch_end(&mut self, event: TouchEvent) {
+        let touches = event.changed_touches();
+        for i in 0..touches.length() {
+            if let Some(touch) = touches.get(i) {
+                let id = touch.identifier();
+                
+                // A single touch lifted without moving is a tap
+                if self.active_touches.len() == 1 && !self.touch_moved {
+                    if let Some(&(x, y)) = self.active_touches.get(&id) {
+                        self.handle_tap(x, y);
+                    }
+                }
+                
+                self.active_touches.remove(&id);
+            }
+        }
+        
+        self.pinch_distance = self.current_pinch_distance();
+    }
+    
+    fn handle_tap(&mut self, canvas_x: f64, canvas_y: f64) {
+        // Convert to world coordinates
+        let (world_x, world_y) = self.screen_to_world(canvas_x, canvas_y);
+        
+        // Select the tapped troop, or clear the selection when tapping empty ground
+        let selection = match self.friendly_troop_at(world_x, world_y) {
+            Some(troop) => vec![troop.id],
+            None => Vec::new(),
+        };
+        self.set_selection(selection);
+    }
+    
+    #[wasm_bindgen]
+    pub fn handle_wheel(&mut self, delta_y: f64) {
+        // Zoom in/out with mouse wheel; trackpads send many small deltas, wheels a few large ones
+        let zoom_factor = (-delta_y * 0.002).clamp(-0.5, 0.5).exp();
+        
+        let focus = self.mouse_screen_position
+            .unwrap_or((self.view_width() / 2.0, self.view_height() / 2.0));
+        self.zoom_by(zoom_factor, focus);
+    }
+    
+    #[wasm_bindgen]
+    pub fn zoom_in(&mut self) {
+        let center = (self.view_width() / 2.0, self.view_height() / 2.0);
+        self.zoom_by(1.25, center);
+    }
+    
+    #[wasm_bindgen]
+    pub fn zoom_out(&mut self) {
+        let center = (self.view_width() / 2.0, self.view_height() / 2.0);
+        self.zoom_by(1.0 / 1.25, center);
+    }
+    
+    fn zoom_by(&mut self, zoom_factor: f64, focus: (f64, f64)) {
+        // Zooming by hand stops following a troop, whichever input it came from
+        self.camera_animation = None;
+        self.followed_troop = None;
+        self.zoom_focus = focus;
+        
+        // Accumulate into the target so quick steps build momentum
+        let target = self.target_zoom.unwrap_or(self.zoom) * zoom_factor;
+        self.target_zoom = Some(target.clamp(self.min_zoom, self.max_zoom));
+    }
+    
+    fn update_zoom_momentum(&mut self) {
+        let target = match self.target_zoom {
+            Some(target) => target,
+            None => return,
+        };
+        
+        // Keep the world point under the zoom focus fixed while zooming
+        let (focus_x, focus_y) = self.zoom_focus;
+        let (before_x, before_y) = self.screen_to_world(focus_x, focus_y);
+        
+        // Ease a fixed fraction of the remaining distance per 60fps frame
+        let frame_time = self.frame_times.back().copied().unwrap_or(16.6);
+        let blend = 1.0 - 0.75_f64.powf(frame_time / 16.6);
+        self.zoom *= (target / self.zoom).powf(blend.clamp(0.0, 1.0));
+        
+        if (target / self.zoom - 1.0).abs() < 0.001 {
+            self.zoom = target;
+            self.target_zoom = None;
+        }
+        
+        let (after_x, after_y) = self.screen_to_world(focus_x, focus_y);
+        self.camera_x += before_x - after_x;
+        self.camera_y += before_y - after_y;
+        self.clamp_camera();
+    }
+    
+    #[wasm_bindgen]
+    pub fn get_camera_x(&self) -> f64 {
+        self.camera_x
+    }
+    
+    #[wasm_bindgen]
+    pub fn get_camera_y(&self) -> f64 {
+        self.camera_y
+    }
+    
+    #[wasm_bindgen]
+    pub fn get_zoom(&self) -> f64 {
+        self.zoom
+    }
+    
+    #[wasm_bindgen]
+    pub fn set_camera(&mut self, x: f64, y: f64, zoom: f64) {
+        self.camera_animation = None;
+        self.target_zoom = None;
+        self.followed_troop = None;
+        self.camera_x = x;
+        self.camera_y = y;
+        self.zoom = zoom.clamp(self.min_zoom, self.max_zoom);
+        self.clamp_camera();
+    }
+    
+    #[wasm_bindgen]
+    pub fn set_zoom_limits(&mut self, min: f64, max: f64) -> Result<(), JsValue> {
+        if !(min > 0.0 && max > 0.0 && min <= max) {
+            return Err(JsValue::from_str(&format!("Invalid zoom limits: {} to {}", min, max)));
+        }
+        
+        self.min_zoom = min;
+        self.max_zoom = max;
+        self.target_zoom = self.target_zoom.map(|target| target.clamp(min, max));
+        
+        // Re-clamp the current zoom, keeping the view centered on the same point
+        let (center_x, center_y) = self.view_center();
+        self.center_camera(center_x, center_y, self.zoom);
+        Ok(())
+    }
+    
+    fn view_center(&self) -> (f64, f64) {
+        // World position at the center of the canvas
+        self.screen_to_world(self.view_width() / 2.0, self.view_height() / 2.0)
+    }
+    
+    fn animate_camera_to(&mut self, world_x: f64, world_y: f64, zoom: f64) {
+        // Ease toward the target over the next frames instead of jumping
+        let (center_x, center_y) = self.view_center();
+        self.target_zoom = None;
+        self.followed_troop = None;
+        self.camera_animation = Some(CameraAnimation {
+            from: (center_x, center_y, self.zoom),
+            to: (world_x, world_y, zoom.clamp(self.min_zoom, self.max_zoom)),
+            started_at: now(),
+        });
+    }
+    
+    fn update_camera_animation(&mut self) {
+        let (from, to, started_at) = match &self.camera_animation {
+            Some(animation) => (animation.from, animation.to, animation.started_at),
+            None => return,
+        };
+        
+        let duration = 250.0;
+        let t = ((now() - started_at) / duration).clamp(0.0, 1.0);
+        let eased = 1.0 - (1.0 - t).powi(3);  // Ease out cubic
+        
+        // Interpolate zoom geometrically so zooming in and out feel equally fast
+        let center_x = from.0 + (to.0 - from.0) * eased;
+        let center_y = from.1 + (to.1 - from.1) * eased;
+        let zoom = from.2 * (to.2 / from.2).powf(eased);
+        self.center_camera(center_x, center_y, zoom);
+        
+        if t >= 1.0 {
+            self.camera_animation = None;
+        }
+    }
+    
+    fn center_camera(&mut self, world_x: f64, world_y: f64, zoom: f64) {
+        self.zoom = zoom.clamp(self.min_zoom, self.max_zoom);
+        self.camera_x = world_x - self.view_width() / self.zoom / 2.0;
+        self.camera_y = world_y - self.view_height() / self.zoom / 2.0;
+        self.clamp_camera();
+    }
+    
+    #[wasm_bindgen]
+    pub fn focus_selection(&mut self) {
+        let game_state = match &self.game_state {
+            Some(game_state) => game_state,
+            None => return,
+        };
+        
+        let positions: Vec<(f64, f64)> = game_state.troops.iter()
+            .filter(|t| self.selected_troops.contains(&t.id))
+            .map(|t| t.position)
+            .collect();
+        
+        if positions.is_empty() {
+            return;
+        }
+        
+        // Bounding box of the selected troops
+        let min_x = positions.iter().map(|p| p.0).fold(f64::INFINITY, f64::min);
+        let max_x = positions.iter().map(|p| p.0).fold(f64::NEG_INFINITY, f64::max);
+        let min_y = positions.iter().map(|p| p.1).fold(f64::INFINITY, f64::min);
+        let max_y = positions.iter().map(|p| p.1).fold(f64::NEG_INFINITY, f64::max);
+        
+        let center_x = (min_x + max_x) / 2.0;
+        let center_y = (min_y + max_y) / 2.0;
+        
+        // Center on a lone troop at a comfortable zoom
+        if positions.len() == 1 {
+            self.animate_camera_to(center_x, center_y, 2.0);
+            return;
+        }
+        
+        // Fit the box into 80% of the canvas, leaving padding around the edges
+        let box_width = (max_x - min_x).max(1.0);
+        let box_height = (max_y - min_y).max(1.0);
+        let zoom_x = self.view_width() * 0.8 / box_width;
+        let zoom_y = self.view_height() * 0.8 / box_height;
+        
+        self.animate_camera_to(center_x, center_y, zoom_x.min(zoom_y));
+    }
+    
+    #[wasm_bindgen]
+    pub fn pan_camera(&mut self, dx: f64, dy: f64) {
+        // Scale by zoom so keyboard panning moves the same number of screen pixels at any zoom,
+        // and undo the camera rotation so the view moves in the direction the screen delta points
+        let (dx, dy) = rotate_point((dx, dy), -self.camera_rotation);
+        self.camera_animation = None;
+        self.followed_troop = None;
+        self.camera_x += dx / self.zoom;
+        self.camera_y += dy / self.zoom;
+        self.clamp_camera();
+    }
+    
+    #[wasm_bindgen]
+    pub fn follow_troop(&mut self, id: u32) {
+        self.camera_animation = None;
+        self.followed_troop = Some(id);
+    }
+    
+    #[wasm_bindgen]
+    pub fn stop_following(&mut self) {
+        self.followed_troop = None;
+    }
+    
+    fn update_follow_camera(&mut self, interpolated: Option<&GameState>) {
+        let id = match self.followed_troop {
+            Some(id) => id,
+            None => return,
+        };
+        let game_state = match interpolated.or(self.game_state.as_ref()) {
+            Some(game_state) => game_state,
+            None => return,
+        };
+        
+        // Stop following once the troop dies or leaves the state
+        match game_state.troops.iter().find(|t| t.id == id).map(|t| t.position) {
+            Some((x, y)) => self.center_camera(x, y, self.zoom),
+            None => self.followed_troop = None,
+        }
+    }
+    
+    #[wasm_bindgen]
+    pub fn rotate_camera(&mut self, delta: f64) {
+        // Rotation is in radians around the center of the canvas
+        self.camera_rotation = (self.camera_rotation + delta).rem_euclid(2.0 * PI);
+    }
+    
+    #[wasm_bindgen]
+    pub fn get_camera_rotation(&self) -> f64 {
+        self.camera_rotation
+    }
+    
+    #[wasm_bindgen]
+    pub fn toggle_edge_scroll(&mut self) {
+        self.edge_scroll_enabled = !self.edge_scroll_enabled;
+    }
+    
+    #[wasm_bindgen]
+    pub fn set_edge_scroll_margin(&mut self, margin: f64) {
+        self.edge_scroll_margin = margin.max(0.0);
+    }
+    
+    #[wasm_bindgen]
+    pub fn update_edge_scroll(&mut self, mouse_x: f64, mouse_y: f64) {
+        // Don't scroll while disabled or while a selection box is being dragged
+        if !self.edge_scroll_enabled || self.selection_start.is_some() {
+            return;
+        }
+        
+        let width = self.view_width();
+        let height = self.view_height();
+        let margin = self.edge_scroll_margin;
+        let step = 10.0;  // Screen pixels per call
+        
+        let mut dx = 0.0;
+        let mut dy = 0.0;
+        
+        if mouse_x < margin {
+            dx -= step;
+        } else if mouse_x > width - margin {
+            dx += step;
+        }
+        
+        if mouse_y < margin {
+            dy -= step;
+        } else if mouse_y > height - margin {
+            dy += step;
+        }
+        
+        if dx != 0.0 || dy != 0.0 {
+            self.pan_camera(dx, dy);
+        }
+    }
+    
+    fn clamp_camera(&mut self) {
+        // Keep the camera over the map; nothing to clamp against without a game state
+        if let Some(game_state) = &self.game_state {
+            let (map_width, map_height) = game_state.map_size;
+            let view_width = self.view_width() / self.zoom;
+            let view_height = self.view_height() / self.zoom;
+            
+            self.camera_x = clamp_camera_axis(self.camera_x, map_width, view_width);
+            self.camera_y = clamp_camera_axis(self.camera_y, map_height, view_height);
+        }
+    }
+    
+    #[wasm_bindgen]
+    pub fn handle_click(&mut self, event: MouseEvent) -> Option<JsValue> {
+        if self.is_dragging || self.player_id.is_none() || self.game_state.is_none() {
+            return None;
+        }
+        
+        let (canvas_x, canvas_y) = self.mouse_position(&event);
+        
+        // Convert canvas coordinates to world coordinates
+        let (world_x, world_y) = self.screen_to_world(canvas_x, canvas_y);
+        
+        // Find the player's position
+        let game_state = self.game_state.as_ref().unwrap();
+        let player_id = self.player_id.unwrap();
+        
+        let player_position = game_state.players.iter()
+            .find(|p| p.id == player_id)
+            .map(|p| p.position);
+        
+        if let Some(position) = player_position {
+            // Calculate direction from player to click point
+            let dx = world_x - position.0;
+            let dy = world_y - position.1;
+            
+            // Create spawn data from the active template
+            let template = self.active_spawn_template();
+            let spawn_data = Object::new();
+            Reflect::set(&spawn_data, &"position".into(), &array_from_tuple(position)).ok()?;
+            Reflect::set(&spawn_data, &"direction".into(), &array_from_tuple((dx, dy))).ok()?;
+            Reflect::set(&spawn_data, &"count".into(), &JsValue::from_f64(template.count as f64)).ok()?;
+            Reflect::set(&spawn_data, &"type".into(), &JsValue::from_str(&template.unit_type)).ok()?;
+            Reflect::set(&spawn_data, &"spread".into(), &JsValue::from_str(&template.spread)).ok()?;
+            
+            return Some(spawn_data.into());
+        }
+        
+        None
+    }
+    
+    #[wasm_bindgen]
+    pub fn set_spawn_unit_type(&mut self, unit_type: String) {
+        // Picking a plain unit type replaces any active template
+        self.spawn_unit_type = unit_type;
+        self.active_template = None;
+    }
+    
+    #[wasm_bindgen]
+    pub fn register_spawn_template(&mut self, name: String, template_js: JsValue) -> Result<(), JsValue> {
+        let template: SpawnTemplate = serde_wasm_bindgen::from_value(template_js)?;
+        self.spawn_templates.insert(name, template);
+        Ok(())
+    }
+    
+    #[wasm_bindgen]
+    pub fn set_active_template(&mut self, name: String) -> Result<(), JsValue> {
+        if !self.spawn_templates.contains_key(&name) {
+            return Err(JsValue::from_str(&format!("Unknown spawn template: {}", name)));
+        }
+        self.active_template = Some(name);
+        Ok(())
+    }
+    
+    fn active_spawn_template(&self) -> SpawnTemplate {
+        // Without a template, spawn the basic group of the chosen unit type
+        match self.active_template.as_ref().and_then(|name| self.spawn_templates.get(name)) {
+            Some(template) => template.clone(),
+            None => SpawnTemplate {
+                unit_type: self.spawn_unit_type.clone(),
+                ..SpawnTemplate::default()
+            },
+        }
+    }
+    
+    #[wasm_bindgen]
+    pub fn handle_right_click(&mut self, event: MouseEvent) -> Option<JsValue> {
+        if self.player_id.is_none() || self.game_state.is_none() || self.selected_troops.is_empty() {
+            return None;
+        }
+        
+        let (canvas_x, canvas_y) = self.mouse_position(&event);
+        
+        // Convert canvas coordinates to world coordinates
+        let (mut world_x, mut world_y) = self.screen_to_world(canvas_x, canvas_y);
+        
+        // While aiming an ability, the click picks its target instead of ordering a move.
+        // Grid snapping is a move-order option, so the target stays where the cursor is.
+        if self.ability_target_radius.is_some() {
+            return self.ability_command(world_x, world_y);
+        }
+        
+        // Snap the destination to the nearest grid intersection. The server receives the
+        // snapped coordinates as the target and must move troops there unmodified.
+        if self.snap_to_grid {
+            (world_x, world_y) = match self.grid_type {
+                GridType::Square => (
+                    (world_x / self.grid_size).round() * self.grid_size,
+                    (world_y / self.grid_size).round() * self.grid_size,
+                ),
+                GridType::Hex => snap_to_hex((world_x, world_y), self.grid_size),
+            };
+        }
+        
+        // Plain moves and Shift-queued waypoints are both undoable
+        self.confirm_move(world_x, world_y);
+        self.record_previous_orders();
+        
+        // Shift queues the position as another waypoint instead of replacing the destination
+        if event.shift_key() {
+            self.queue_waypoint(world_x, world_y);
+            
+            let waypoint_data = Object::new();
+            Reflect::set(&waypoint_data, &"troop_ids".into(), &self.get_selected_troops()).ok()?;
+            
+            let waypoints = Array::new();
+            for &waypoint in &self.waypoints {
+                waypoints.push(&array_from_tuple(waypoint));
+            }
+            Reflect::set(&waypoint_data, &"waypoints".into(), &waypoints).ok()?;
+            Reflect::set(&waypoint_data, &"append".into(), &JsValue::TRUE).ok()?;
+            
+            return Some(waypoint_data.into());
+        }
+        
+        // A plain move order replaces any queued waypoints
+        self.waypoints.clear();
+        self.waypoint_troops.clear();
+        
+        // Ctrl or the attack-move key turns the order into an attack-move
+        self.move_command(world_x, world_y, event.ctrl_key() || self.attack_move_modifier)
+    }
+    
+    fn move_command(&self, world_x: f64, world_y: f64, attack_move: bool) -> Option<JsValue> {
+        // Create move data
+        let move_data = Object::new();
+        Reflect::set(&move_data, &"target_position".into(), &array_from_tuple((world_x, world_y))).ok()?;
+        
+        // Add selected troop IDs
+        Reflect::set(&move_data, &"troop_ids".into(), &self.get_selected_troops()).ok()?;
+        
+        // Spread troops into a formation around the target. `target_position` stays as the
+        // formation center so servers without per-troop targets still get a usable order.
+        if self.formation_moves {
+            let offsets = formation_offsets(self.selected_troops.len(), 15.0);
+            let targets = Array::new();
+            for (&id, (offset_x, offset_y)) in self.selected_troops.iter().zip(offsets) {
+                let target = Object::new();
+                Reflect::set(&target, &"troop_id".into(), &JsValue::from_f64(id as f64)).ok()?;
+                Reflect::set(&target, &"target_position".into(), &array_from_tuple((world_x + offset_x, world_y + offset_y))).ok()?;
+                targets.push(&target);
+            }
+            Reflect::set(&move_data, &"targets".into(), &targets).ok()?;
+        }
+        
+        if attack_move {
+            Reflect::set(&move_data, &"attack_move".into(), &JsValue::TRUE).ok()?;
+        }
+        
+        Some(move_data.into())
+    }
+    
+    #[wasm_bindgen]
+    pub fn stop_selected(&self) -> Option<JsValue> {
+        self.selection_command("stop")
+    }
+    
+    fn selection_command(&self, command: &str) -> Option<JsValue> {
+        if self.selected_troops.is_empty() {
+            return None;
+        }
+        
+        // Create a command without a target for the selected troops
+        let command_data = Object::new();
+        Reflect::set(&command_data, &"command".into(), &command.into()).ok()?;
+        Reflect::set(&command_data, &"troop_ids".into(), &self.get_selected_troops()).ok()?;
+        
+        Some(command_data.into())
+    }
+    
+    #[wasm_bindgen]
+    pub fn begin_ability_target(&mut self, radius: f64) {
+        if radius > 0.0 && radius.is_finite() {
+            self.ability_target_radius = Some(radius);
+        }
+    }
+    
+    #[wasm_bindgen]
+    pub fn cancel_ability_target(&mut self) {
+        self.ability_target_radius = None;
+    }
+    
+    fn ability_command(&mut self, world_x: f64, world_y: f64) -> Option<JsValue> {
+        // Targeting ends with the click that picks the target
+        self.ability_target_radius = None;
+        
+        let ability_data = Object::new();
+        Reflect::set(&ability_data, &"ability_target".into(), &array_from_tuple((world_x, world_y))).ok()?;
+        Reflect::set(&ability_data, &"troop_ids".into(), &self.get_selected_troops()).ok()?;
+        
+        Some(ability_data.into())
+    }
+    
+    fn render_ability_reticle(&self) {
+        let (radius, (screen_x, screen_y)) = match (self.ability_target_radius, self.mouse_screen_position) {
+            (Some(radius), Some(position)) => (radius, position),
+            _ => return,
+        };
+        
+        // Convert from the screen so the reticle stays under the cursor while the camera moves
+        let (x, y) = self.screen_to_world(screen_x, screen_y);
+        
+        self.context.save();
+        
+        // Apply camera transform
+        self.apply_camera_transform(&self.context);
+        
+        // Draw the affected area
+        self.context.set_fill_style(&JsValue::from_str("rgba(255, 60, 0, 0.15)"));
+        self.context.set_stroke_style(&JsValue::from_str("rgba(255, 60, 0, 0.9)"));
+        self.context.set_line_width(2.0 / self.zoom);
+        self.context.begin_path();
+        self.context.arc(x, y, radius, 0.0, 2.0 * PI).unwrap();
+        self.context.fill();
+        self.context.stroke();
+        
+        // Draw a crosshair at the center, a fixed size on screen
+        let arm = 8.0 / self.zoom;
+        self.context.begin_path();
+        self.context.move_to(x - arm, y);
+        self.context.line_to(x + arm, y);
+        self.context.move_to(x, y - arm);
+        self.context.line_to(x, y + arm);
+        self.context.stroke();
+        
+        self.context.restore();
+    }
+    
+    #[wasm_bindgen]
+    pub fn open_command_menu(&mut self, screen_x: f64, screen_y: f64) {
+        if !self.selected_troops.is_empty() {
+            self.command_menu = Some((screen_x, screen_y));
+        }
+    }
+    
+    #[wasm_bindgen]
+    pub fn close_command_menu(&mut self) {
+        self.command_menu = None;
+    }
+    
+    #[wasm_bindgen]
+    pub fn select_command_slice(&mut self, angle: f64) -> Option<JsValue> {
+        // Angle in radians from the menu center, clockwise from the right as on screen
+        let (menu_x, menu_y) = self.command_menu.take()?;
+        
+        match COMMAND_MENU_SLICES[command_slice_at(angle)] {
+            // Move orders target the point the menu was opened on
+            command @ ("move" | "attack") => {
+                let (world_x, world_y) = self.screen_to_world(menu_x, menu_y);
+                self.confirm_move(world_x, world_y);
+                self.record_previous_orders();
+                self.waypoints.clear();
+                self.waypoint_troops.clear();
+                self.move_command(world_x, world_y, command == "attack")
+            },
+            command => self.selection_command(command),
+        }
+    }
+    
+    fn render_command_menu(&self) {
+        let (center_x, center_y) = match self.command_menu {
+            Some(center) => center,
+            None => return,
+        };
+        let outer_radius = 70.0;
+        let inner_radius = 20.0;
+        let slice_angle = 2.0 * PI / COMMAND_MENU_SLICES.len() as f64;
+        
+        // Highlight the slice the cursor points at, once it leaves the dead zone in the middle
+        let hovered = self.mouse_screen_position.and_then(|(x, y)| {
+            let (dx, dy) = (x - center_x, y - center_y);
+            if dx * dx + dy * dy < inner_radius * inner_radius {
+                None
+            } else {
+                Some(command_slice_at(dy.atan2(dx)))
+            }
+        });
+        
+        self.context.save();
+        self.context.set_font("13px Arial");
+        self.context.set_text_align("center");
+        self.context.set_text_baseline("middle");
+        self.context.set_stroke_style(&JsValue::from_str("rgba(255, 255, 255, 0.6)"));
+        self.context.set_line_width(1.0);
+        
+        for (i, command) in COMMAND_MENU_SLICES.iter().enumerate() {
+            // Each slice is centered on its direction
+            let middle = i as f64 * slice_angle;
+            let start = middle - slice_angle / 2.0;
+            let end = middle + slice_angle / 2.0;
+            
+            let fill = if hovered == Some(i) { "rgba(60, 120, 60, 0.85)" } else { "rgba(0, 0, 0, 0.7)" };
+            self.context.set_fill_style(&JsValue::from_str(fill));
+            self.context.begin_path();
+            self.context.arc(center_x, center_y, outer_radius, start, end).unwrap();
+            self.context.arc_with_anticlockwise(center_x, center_y, inner_radius, end, start, true).unwrap();
+            self.context.close_path();
+            self.context.fill();
+            self.context.stroke();
+            
+            let label_radius = (outer_radius + inner_radius) / 2.0;
+            self.context.set_fill_style(&JsValue::from_str("#ffffff"));
+            self.context.fill_text(
+                &capitalize(command),
+                center_x + middle.cos() * label_radius,
+                center_y + middle.sin() * label_radius,
+            ).unwrap();
+        }
+        
+        self.context.restore();
+    }
+    
+    fn record_previous_orders(&mut self) {
+        let game_state = match &self.game_state {
+            Some(game_state) => game_state,
+            None => return,
+        };
+        
+        // Idle troops had no destination, so undoing sends them back to where they stood
+        self.previous_orders = game_state.troops.iter()
+            .filter(|t| self.selected_troops.contains(&t.id))
+            .map(|t| PreviousOrder {
+                troop_id: t.id,
+                target_position: t.target_position.unwrap_or(t.position),
+                target: t.target,
+            })
+            .collect();
+    }
+    
+    #[wasm_bindgen]
+    pub fn undo_last_command(&mut self) -> Option<JsValue> {
+        // Each command can be undone once
+        let previous_orders = std::mem::take(&mut self.previous_orders);
+        if previous_orders.is_empty() {
+            return None;
+        }
+        
+        // Undoing a Shift-queued waypoint re-issues the destination from before it, which
+        // replaces the whole queue, so forget the queued waypoints here too
+        self.waypoints.clear();
+        self.waypoint_troops.clear();
+        
+        // Re-issue each troop's previous destination and attack target
+        let undo_data = Object::new();
+        let troop_ids = Array::new();
+        let targets = Array::new();
+        for order in &previous_orders {
+            troop_ids.push(&JsValue::from_f64(order.troop_id as f64));
+            
+            let target = Object::new();
+            Reflect::set(&target, &"troop_id".into(), &JsValue::from_f64(order.troop_id as f64)).ok()?;
+            Reflect::set(&target, &"target_position".into(), &array_from_tuple(order.target_position)).ok()?;
+            if let Some(target_id) = order.target {
+                Reflect::set(&target, &"target".into(), &JsValue::from_f64(target_id as f64)).ok()?;
+            }
+            targets.push(&target);
+        }
+        Reflect::set(&undo_data, &"troop_ids".into(), &troop_ids).ok()?;
+        Reflect::set(&undo_data, &"targets".into(), &targets).ok()?;
+        
+        Some(undo_data.into())
+    }
+    
+    fn confirm_move(&mut self, world_x: f64, world_y: f64) {
+        let game_state = match &self.game_state {
+            Some(game_state) => game_state,
+            None => return,
+        };
+        
+        // Draw the confirmation line from the centroid of the selected troops
+        let positions: Vec<(f64, f64)> = game_state.troops.iter()
+            .filter(|t| self.selected_troops.contains(&t.id))
+            .map(|t| t.position)
+            .collect();
+        if positions.is_empty() {
+            return;
+        }
+        let count = positions.len() as f64;
+        let origin = (
+            positions.iter().map(|p| p.0).sum::<f64>() / count,
+            positions.iter().map(|p| p.1).sum::<f64>() / count,
+        );
+        
+        self.move_confirmation = Some(MoveConfirmation {
+            origin,
+            target: (world_x, world_y),
+            created_at: now(),
+        });
+    }
+    
+    fn queue_waypoint(&mut self, world_x: f64, world_y: f64) {
+        // Start a new path when the selection differs from the one the queue belongs to
+        if !same_troop_set(&self.waypoint_troops, &self.selected_troops) {
+            self.waypoints.clear();
+            self.waypoint_troops = self.selected_troops.clone();
+        }
+        
+        self.waypoints.push((world_x, world_y));
+    }
+    
+    #[wasm_bindgen]
+    pub fn set_formation_moves(&mut self, enabled: bool) {
+        // Disable for servers that only understand a single shared target
+        self.formation_moves = enabled;
+    }
+    
+    #[wasm_bindgen]
+    pub fn toggle_snap_to_grid(&mut self) {
+        self.snap_to_grid = !self.snap_to_grid;
+    }
+    
+    #[wasm_bindgen]
+    pub fn set_attack_move_modifier(&mut self, active: bool) {
+        // Called by JavaScript while the attack-move key is held
+        self.attack_move_modifier = active;
+    }
+    
+    #[wasm_bindgen]
+    pub fn get_selected_troops(&self) -> JsValue {
+        let selected_array = Array::new();
+        for &id in &self.selected_troops {
+            selected_array.push(&JsValue::from_f64(id as f64));
+        }
+        selected_array.into()
+    }
+    
+    #[wasm_bindgen]
+    pub fn render(&mut self) {
+        self.record_frame_time();
+        self.expire_effects();
+        self.update_camera_animation();
+        self.update_zoom_momentum();
+        self.render_stats.set(RenderStats::default());
+        
+        // Draw in CSS pixels; the backing canvas may be larger on high-DPI screens
+        self.context.save();
+        self.context.scale(self.pixel_ratio, self.pixel_ratio).unwrap();
+        self.clear_canvas();
+        
+        let interpolated = self.interpolated_state();
+        self.update_follow_camera(interpolated.as_ref());
+        
+        // Keep the interpolated state alongside its own index, so visibility checks
+        // during the pass can query it spatially
+        if let Some(state) = &interpolated {
+            self.interpolated_index.rebuild(&state.troops);
+        }
+        self.interpolated = interpolated;
+        
+        if let Some(game_state) = self.interpolated.as_ref().or(self.game_state.as_ref()) {
+            self.render_world(game_state);
+            
+            if self.ruler_enabled {
+                self.render_ruler();
+            } else {
+                self.render_selection_box();
+            }
+            
+            self.render_ability_reticle();
+            
+            if self.show_offscreen_indicators {
+                self.render_offscreen_indicators(game_state);
+            }
+            
+            if self.show_minimap {
+                self.render_minimap(game_state);
+            }
+            
+            if self.show_scale_bar {
+                self.render_scale_bar(game_state);
+            }
+            
+            self.render_hover_tooltip(game_state);
+            self.render_event_log();
+            
+            if !self.selected_troops.is_empty() {
+                self.render_selection_summary(game_state);
+            }
+            
+            if self.show_dev_tools {
+                self.render_dev_tools();
+            }
+        }
+        
+        self.render_command_menu();
+        
+        self.context.restore();
+        
+        if !self.secondary_views.is_empty() {
+            self.render_secondary_views();
+        }
+    }
+    
+    fn render_world(&self, game_state: &GameState) {
+        self.render_grid(game_state);
+        self.render_terrain(game_state);
+        self.render_players(game_state);
+        self.render_rally_point(game_state);
+        self.render_troops(game_state);
+        self.render_death_markers();
+        self.render_waypoints();
+        self.render_move_confirmation();
+        self.render_group_facing(game_state);
+        self.render_projectiles(game_state);
+        self.render_impact_markers();
+        self.render_damage_numbers();
+        
+        if self.show_dev_tools && self.show_troop_ids {
+            self.render_troop_ids(game_state);
+        }
+        
+        // Names would turn into an unreadable pile when zoomed far out
+        if self.show_names && self.zoom >= 0.75 {
+            self.render_troop_names(game_state);
+        }
+        
+        if self.fog_enabled {
+            self.render_fog(game_state);
+        }
+    }
+    
+    #[wasm_bindgen]
+    pub fn add_secondary_view(&mut self, canvas_id: &str) -> Result<usize, JsValue> {
+        let document = web_sys::window().unwrap().document().unwrap();
+        let canvas = document.get_element_by_id(canvas_id)
+            .ok_or_else(|| JsValue::from_str(&format!("Unknown canvas: {}", canvas_id)))?
+            .dyn_into::<HtmlCanvasElement>()?;
+        
+        let context = canvas
+            .get_context("2d")?
+            .unwrap()
+            .dyn_into::<CanvasRenderingContext2d>()?;
+        
+        let fog_canvas = document.create_element("canvas")?
+            .dyn_into::<HtmlCanvasElement>()?;
+        
+        let fog_context = fog_canvas
+            .get_context("2d")?
+            .unwrap()
+            .dyn_into::<CanvasRenderingContext2d>()?;
+        
+        self.secondary_views.push(SecondaryView {
+            canvas,
+            context,
+            fog_canvas,
+            fog_context,
+            camera_x: 0.0,
+            camera_y: 0.0,
+            zoom: 1.0,
+            camera_rotation: 0.0,
+            pixel_ratio: 1.0,
+            fit_map: true,
+        });
+        Ok(self.secondary_views.len() - 1)
+    }
+    
+    #[wasm_bindgen]
+    pub fn set_secondary_view_camera(&mut self, index: usize, x: f64, y: f64, zoom: f64) -> Result<(), JsValue> {
+        let view = self.secondary_views.get_mut(index)
+            .ok_or_else(|| JsValue::from_str(&format!("Unknown view: {}", index)))?;
+        
+        view.camera_x = x;
+        view.camera_y = y;
+        view.zoom = zoom.clamp(self.min_zoom, self.max_zoom);
+        view.fit_map = false;
+        Ok(())
+    }
+    
+    #[wasm_bindgen]
+    pub fn clear_secondary_views(&mut self) {
+        self.secondary_views.clear();
+    }
+    
+    fn render_secondary_views(&mut self) {
+        // Render stats describe the main view only
+        let stats = self.render_stats.get();
+        let map_size = self.interpolated.as_ref().or(self.game_state.as_ref()).map(|state| state.map_size);
+        let mut views = std::mem::take(&mut self.secondary_views);
+        
+        for view in &mut views {
+            // Draw with the view's canvas and camera by swapping them in for the main ones,
+            // so every render step works unchanged
+            self.swap_view(view);
+            self.drawing_secondary_view = true;
+            
+            if let (true, Some(map_size)) = (view.fit_map, map_size) {
+                self.fit_camera_to_map(map_size);
+            }
+            
+            self.context.save();
+            self.context.scale(self.pixel_ratio, self.pixel_ratio).unwrap();
+            self.clear_canvas();
+            if let Some(game_state) = self.interpolated.as_ref().or(self.game_state.as_ref()) {
+                self.render_world(game_state);
+            }
+            self.context.restore();
+            
+            self.drawing_secondary_view = false;
+            self.swap_view(view);
+        }
+        
+        self.secondary_views = views;
+        self.render_stats.set(stats);
+    }
+    
+    fn swap_view(&mut self, view: &mut SecondaryView) {
+        std::mem::swap(&mut self.canvas, &mut view.canvas);
+        std::mem::swap(&mut self.context, &mut view.context);
+        std::mem::swap(&mut self.fog_canvas, &mut view.fog_canvas);
+        std::mem::swap(&mut self.fog_context, &mut view.fog_context);
+        std::mem::swap(&mut self.camera_x, &mut view.camera_x);
+        std::mem::swap(&mut self.camera_y, &mut view.camera_y);
+        std::mem::swap(&mut self.zoom, &mut view.zoom);
+        std::mem::swap(&mut self.camera_rotation, &mut view.camera_rotation);
+        std::mem::swap(&mut self.pixel_ratio, &mut view.pixel_ratio);
+    }
+    
+    fn fit_camera_to_map(&mut self, map_size: (f64, f64)) {
+        // Show the whole map, ignoring the zoom limits meant for the main view
+        let (map_width, map_height) = map_size;
+        self.zoom = (self.view_width() / map_width).min(self.view_height() / map_height);
+        self.camera_x = map_width / 2.0 - self.view_width() / self.zoom / 2.0;
+        self.camera_y = map_height / 2.0 - self.view_height() / self.zoom / 2.0;
+    }
+    
+    fn record_frame_time(&mut self) {
+        let now = now();
+        
+        if let Some(last) = self.last_frame_time {
+            self.frame_times.push_back(now - last);
+            
+            // Keep only the most recent frames
+            while self.frame_times.len() > 120 {
+                self.frame_times.pop_front();
+            }
+        }
+        
+        self.last_frame_time = Some(now);
+    }
+    
+    fn expire_effects(&mut self) {
+        let now = now();
+        self.death_markers.retain(|marker| now - marker.created_at < 500.0);
+        self.impact_markers.retain(|marker| now - marker.created_at < 200.0);
+        self.damage_numbers.retain(|number| now - number.created_at < 800.0);
+        self.event_log.retain(|(_, created_at)| now - created_at < 5000.0);
+        if self.move_confirmation.as_ref().is_some_and(|confirmation| now - confirmation.created_at >= 300.0) {
+            self.move_confirmation = None;
+        }
+    }
+    
+    fn render_death_markers(&self) {
+        if self.death_markers.is_empty() {
+            return;
+        }
+        
+        let view = self.visible_world_rect();
+        
+        let now = now();
+        
+        self.context.save();
+        
+        // Apply camera transform
+        self.apply_camera_transform(&self.context);
+        
+        // Draw a shrinking, fading burst where each troop died
+        for marker in &self.death_markers {
+            let (x, y) = marker.position;
+            if !in_view(view, (x, y), 20.0) {
+                continue;
+            }
+            
+            let progress = ((now - marker.created_at) / 500.0).clamp(0.0, 1.0);
+            let (r, g, b) = self.apply_colorblind(marker.color);
+            
+            self.context.set_fill_style(&JsValue::from_str(&format!("rgba({}, {}, {}, {})", r, g, b, 1.0 - progress)));
+            self.context.begin_path();
+            self.context.arc(x, y, 8.0 * (1.0 - progress) + 1.0, 0.0, 2.0 * PI).unwrap();
+            self.context.fill();
+            
+            self.context.set_stroke_style(&JsValue::from_str(&format!("rgba(255, 255, 255, {})", 0.8 * (1.0 - progress))));
+            self.context.set_line_width(1.0);
+            self.context.begin_path();
+            self.context.arc(x, y, 5.0 + 10.0 * progress, 0.0, 2.0 * PI).unwrap();
+            self.context.stroke();
+        }
+        
+        self.context.restore();
+    }
+    
+    fn render_group_facing(&self, game_state: &GameState) {
+        let selected: Vec<&Troop> = game_state.troops.iter()
+            .filter(|t| self.selected_troops.contains(&t.id))
+            .collect();
+        if selected.len() < 2 {
+            return;
+        }
+        
+        // Centroid and mean normalized direction of the selected troops
+        let count = selected.len() as f64;
+        let center_x = selected.iter().map(|t| t.position.0).sum::<f64>() / count;
+        let center_y = selected.iter().map(|t| t.position.1).sum::<f64>() / count;
+        let (mut dir_x, mut dir_y) = (0.0, 0.0);
+        for troop in &selected {
+            let (dx, dy) = troop.direction;
+            let length = (dx * dx + dy * dy).sqrt();
+            if length > 0.0 {
+                dir_x += dx / length / count;
+                dir_y += dy / length / count;
+            }
+        }
+        
+        // Skip when the group is mostly stationary or heading in different directions
+        let strength = (dir_x * dir_x + dir_y * dir_y).sqrt();
+        if strength < 0.3 {
+            return;
+        }
+        
+        // Draw in screen space so the arrow keeps its size at any zoom
+        let (screen_x, screen_y) = self.world_to_screen(center_x, center_y);
+        let angle = facing_angle((dir_x, dir_y)) + self.camera_rotation;
+        let (tip_x, tip_y) = rotate_point((30.0, 0.0), angle);
+        let (left_x, left_y) = rotate_point((20.0, -6.0), angle);
+        let (right_x, right_y) = rotate_point((20.0, 6.0), angle);
+        
+        self.context.save();
+        self.context.set_stroke_style(&JsValue::from_str("rgba(0, 255, 0, 0.7)"));
+        self.context.set_fill_style(&JsValue::from_str("rgba(0, 255, 0, 0.7)"));
+        self.context.set_line_width(2.0);
+        
+        self.context.begin_path();
+        self.context.move_to(screen_x, screen_y);
+        self.context.line_to(screen_x + tip_x, screen_y + tip_y);
+        self.context.stroke();
+        
+        self.context.begin_path();
+        self.context.move_to(screen_x + tip_x, screen_y + tip_y);
+        self.context.line_to(screen_x + left_x, screen_y + left_y);
+        self.context.line_to(screen_x + right_x, screen_y + right_y);
+        self.context.close_path();
+        self.context.fill();
+        
+        self.context.restore();
+    }
+    
+    fn render_move_confirmation(&self) {
+        let confirmation = match &self.move_confirmation {
+            Some(confirmation) => confirmation,
+            None => return,
+        };
+        
+        let progress = ((now() - confirmation.created_at) / 300.0).clamp(0.0, 1.0);
+        let (origin_x, origin_y) = confirmation.origin;
+        let (target_x, target_y) = confirmation.target;
+        
+        self.context.save();
+        
+        // Apply camera transform
+        self.apply_camera_transform(&self.context);
+        
+        // Fading line from the selected troops to the destination
+        self.context.set_stroke_style(&JsValue::from_str(&format!("rgba(0, 255, 0, {})", 0.6 * (1.0 - progress))));
+        self.context.set_line_width(1.0 / self.zoom);
+        self.context.begin_path();
+        self.context.move_to(origin_x, origin_y);
+        self.context.line_to(target_x, target_y);
+        self.context.stroke();
+        
+        // Shrinking pulse at the destination, sized in screen pixels
+        self.context.set_line_width(2.0 / self.zoom);
+        self.context.set_stroke_style(&JsValue::from_str(&format!("rgba(0, 255, 0, {})", 1.0 - progress)));
+        self.context.begin_path();
+        self.context.arc(target_x, target_y, (4.0 + 12.0 * (1.0 - progress)) / self.zoom, 0.0, 2.0 * PI).unwrap();
+        self.context.stroke();
+        
+        self.context.restore();
+    }
+    
+    fn render_damage_numbers(&self) {
+        if self.damage_numbers.is_empty() {
+            return;
+        }
+        
+        let camera = self.camera();
+        let view = camera.visible_world_rect();
+        
+        let now = now();
+        
+        // Drawn in screen space so the numbers stay readable at any zoom
+        self.context.save();
+        self.context.set_font("bold 12px Arial");
+        self.context.set_text_align("center");
+        
+        // Rise and fade above the damaged troop
+        for number in &self.damage_numbers {
+            let (x, y) = number.position;
+            if !in_view(view, (x, y), 20.0) {
+                continue;
+            }
+            
+            let progress = ((now - number.created_at) / 800.0).clamp(0.0, 1.0);
+            let (screen_x, screen_y) = camera.world_to_screen(x, y);
+            let text_y = screen_y - 15.0 - 20.0 * progress;
+            
+            self.context.set_fill_style(&JsValue::from_str(&format!("rgba(255, 80, 80, {})", 1.0 - progress)));
+            self.context.fill_text(&format!("-{:.0}", number.amount), screen_x, text_y).unwrap();
+        }
+        
+        self.context.restore();
+    }
+    
+    fn render_impact_markers(&self) {
+        if self.impact_markers.is_empty() {
+            return;
+        }
+        
+        let view = self.visible_world_rect();
+        
+        let now = now();
+        
+        self.context.save();
+        
+        // Apply camera transform
+        self.apply_camera_transform(&self.context);
+        
+        // Draw an expanding, fading ring where each projectile landed
+        self.context.set_line_width(1.5);
+        for marker in &self.impact_markers {
+            let (x, y) = marker.position;
+            if !in_view(view, (x, y), 10.0) {
+                continue;
+            }
+            
+            let progress = ((now - marker.created_at) / 200.0).clamp(0.0, 1.0);
+            let (r, g, b) = self.apply_colorblind(marker.color);
+            
+            self.context.set_stroke_style(&JsValue::from_str(&format!("rgba({}, {}, {}, {})", r, g, b, 1.0 - progress)));
+            self.context.begin_path();
+            self.context.arc(x, y, 2.0 + 6.0 * progress, 0.0, 2.0 * PI).unwrap();
+            self.context.stroke();
+        }
+        
+        self.context.restore();
+    }
+    
+    fn render_selection_box(&self) {
+        if let (Some(start), Some(end)) = (self.selection_start, self.selection_end) {
+            self.context.save();
+            
+            // Draw selection box
+            let min_x = start.0.min(end.0);
+            let max_x = start.0.max(end.0);
+            let min_y = start.1.min(end.1);
+            let max_y = start.1.max(end.1);
+            
+            self.context.set_stroke_style(&JsValue::from_str("rgba(0, 255, 0, 0.8)"));
+            self.context.set_line_width(1.0);
+            self.context.set_fill_style(&JsValue::from_str("rgba(0, 255, 0, 0.2)"));
+            
+            self.context.begin_path();
+            match self.selection_mode {
+                SelectionMode::Box => self.context.rect(min_x, min_y, max_x - min_x, max_y - min_y),
+                SelectionMode::Circle => {
+                    let radius = ((end.0 - start.0).powi(2) + (end.1 - start.1).powi(2)).sqrt();
+                    self.context.arc(start.0, start.1, radius, 0.0, 2.0 * PI).unwrap();
+                },
+            }
+            self.context.fill();
+            self.context.stroke();
+            
+            // Draw the live count next to the cursor
+            if let Some(count) = self.tentative_selection_count {
+                self.context.set_font("12px Arial");
+                self.context.set_fill_style(&JsValue::from_str("#00ff00"));
+                self.context.fill_text(&count.to_string(), end.0 + 12.0, end.1 - 8.0).unwrap();
+            }
+            
+            self.context.restore();
+        }
+    }
+    
+    fn render_ruler(&self) {
+        if let (Some(start), Some(end)) = (self.selection_start, self.selection_end) {
+            self.context.save();
+            
+            // Draw in screen space so the line and label stay a fixed size
+            let (start_x, start_y) = start;
+            let (end_x, end_y) = end;
+            
+            // Draw measuring line
+            self.context.set_stroke_style(&JsValue::from_str("#ffff00"));
+            self.context.set_line_width(1.0);
+            self.context.begin_path();
+            self.context.move_to(start_x, start_y);
+            self.context.line_to(end_x, end_y);
+            self.context.stroke();
+            
+            // Draw world-space distance next to the cursor
+            let (start_world_x, start_world_y) = self.screen_to_world(start_x, start_y);
+            let (end_world_x, end_world_y) = self.screen_to_world(end_x, end_y);
+            let distance = ((end_world_x - start_world_x).powi(2) + (end_world_y - start_world_y).powi(2)).sqrt();
+            self.context.set_font("14px Arial");
+            self.context.set_fill_style(&JsValue::from_str("#ffff00"));
+            self.context.fill_text(&format!("{:.0}", distance), end_x + 10.0, end_y - 10.0).unwrap();
+            
+            self.context.restore();
+        }
+    }
+    
+    #[wasm_bindgen]
+    pub fn set_pixel_ratio(&mut self, ratio: f64) {
+        // Call with window.devicePixelRatio, and again if the canvas is resized outside resize()
+        if ratio <= 0.0 {
+            return;
+        }
+        
+        // Keep the displayed size, falling back to the current logical size if not laid out
+        let rect = self.canvas.get_bounding_client_rect();
+        let css_width = if rect.width() > 0.0 { rect.width() } else { self.view_width() };
+        let css_height = if rect.height() > 0.0 { rect.height() } else { self.view_height() };
+        self.pixel_ratio = ratio;
+        self.set_canvas_size(css_width, css_height);
+    }
+    
+    #[wasm_bindgen]
+    pub fn resize(&mut self, width: u32, height: u32) {
+        // Call from the window resize event with the new size in CSS pixels, instead of
+        // rebuilding the renderer. The world point at the center of the view stays centered.
+        let (center_x, center_y) = self.view_center();
+        self.set_canvas_size(width as f64, height as f64);
+        self.center_camera(center_x, center_y, self.zoom);
+    }
+    
+    fn set_canvas_size(&self, css_width: f64, css_height: f64) {
+        // Size the backing store in device pixels and pin the displayed size in CSS pixels
+        self.canvas.set_width((css_width * self.pixel_ratio).round() as u32);
+        self.canvas.set_height((css_height * self.pixel_ratio).round() as u32);
+        let style = self.canvas.style();
+        style.set_property("width", &format!("{}px", css_width)).unwrap();
+        style.set_property("height", &format!("{}px", css_height)).unwrap();
+    }
+    
+    fn view_width(&self) -> f64 {
+        // Canvas width in CSS pixels, which all drawing and input math works in
+        self.canvas.width() as f64 / self.pixel_ratio
+    }
+    
+    fn view_height(&self) -> f64 {
+        self.canvas.height() as f64 / self.pixel_ratio
+    }
+    
+    #[wasm_bindgen]
+    pub fn set_theme(&mut self, theme_js: JsValue) -> Result<(), JsValue> {
+        // Missing colors fall back to the default dark theme
+        self.theme = serde_wasm_bindgen::from_value(theme_js)?;
+        Ok(())
+    }
+    
+    fn clear_canvas(&self) {
+        let width = self.view_width();
+        let height = self.view_height();
+        
+        self.context.save();
+        self.context.set_fill_style(&JsValue::from_str(&self.theme.background));
+        self.context.fill_rect(0.0, 0.0, width, height);
+        self.context.restore();
+    }
+    
+    fn render_terrain(&self, game_state: &GameState) {
+        if game_state.obstacles.is_empty() {
+            return;
+        }
+        
+        let (min_x, min_y, max_x, max_y) = self.visible_world_rect();
+        
+        self.context.save();
+        
+        // Apply camera transform
+        self.apply_camera_transform(&self.context);
+        
+        // Draw on-screen obstacles as a single path, filled and then outlined
+        self.context.begin_path();
+        for &(x, y, width, height) in &game_state.obstacles {
+            if x > max_x || y > max_y || x + width < min_x || y + height < min_y {
+                continue;
+            }
+            self.context.rect(x, y, width, height);
+        }
+        self.context.set_fill_style(&JsValue::from_str("#3a3226"));
+        self.context.fill();
+        self.context.set_stroke_style(&JsValue::from_str("#5a4d3a"));
+        self.context.set_line_width(1.0);
+        self.context.stroke();
+        
+        self.context.restore();
+    }
+    
+    fn render_grid(&self, game_state: &GameState) {
+        let (map_width, map_height) = game_state.map_size;
+        let grid_size = self.grid_size;
+        
+        self.context.save();
+        
+        // Apply camera transform
+        self.apply_camera_transform(&self.context);
+        
+        self.render_out_of_bounds(map_width, map_height);
+        
+        // Draw grid
+        if self.show_grid && self.grid_type == GridType::Hex {
+            self.render_hex_grid(map_width, map_height, grid_size);
+        } else if self.show_grid {
+            self.context.set_line_width(1.0 / self.zoom);
             
-            // Calculate selection box
-            let min_x = start.0.min(end.0);
-            let max_x = start.0.max(end.0);
-            let min_y = start.1.min(end.1);
-            let max_y = start.1.max(end.1);
+            // Fade fine lines out as they crowd together on screen, hiding them below 8 pixels apart
+            let spacing = grid_size * self.zoom;
+            let fine_alpha = ((spacing - 8.0) / 12.0).clamp(0.0, 1.0);
+            if fine_alpha > 0.0 && self.quality > 0 {
+                self.context.set_global_alpha(fine_alpha);
+                self.context.set_stroke_style(&JsValue::from_str(&self.theme.grid_minor));
+                self.stroke_grid_lines(map_width, map_height, grid_size);
+                self.context.set_global_alpha(1.0);
+            }
+            
+            let major_step = major_grid_step(grid_size, self.zoom);
+            self.context.set_stroke_style(&JsValue::from_str(&self.theme.grid_major));
+            self.stroke_grid_lines(map_width, map_height, major_step);
+        }
+        
+        // Draw map border
+        self.context.set_stroke_style(&JsValue::from_str(&self.theme.border));
+        self.context.set_line_width(2.0);
+        self.context.stroke_rect(0.0, 0.0, map_width, map_height);
+        
+        self.context.restore();
+        
+        if self.show_grid_labels {
+            self.render_grid_labels(game_state, grid_size);
+        }
+    }
+    
+    fn render_out_of_bounds(&self, map_width: f64, map_height: f64) {
+        let (min_x, min_y, max_x, max_y) = self.visible_world_rect();
+        
+        // Visible strips beyond each map edge; the corners belong to the left and right strips
+        let strips = [
+            (min_x, min_y, 0.0, max_y),
+            (map_width, min_y, max_x, max_y),
+            (0.0, min_y, map_width, 0.0),
+            (0.0, map_height, map_width, max_y),
+        ];
+        let strips: Vec<_> = strips.iter()
+            .filter(|(left, top, right, bottom)| right > left && bottom > top)
+            .collect();
+        if strips.is_empty() {
+            return;
+        }
+        
+        self.context.save();
+        self.context.begin_path();
+        for (left, top, right, bottom) in &strips {
+            self.context.rect(*left, *top, right - left, bottom - top);
+        }
+        self.context.set_fill_style(&JsValue::from_str(&self.theme.out_of_bounds));
+        self.context.fill();
+        self.context.clip();
+        
+        // Hatch with diagonal lines a fixed screen distance apart
+        let spacing = 16.0 / self.zoom;
+        self.context.set_stroke_style(&JsValue::from_str(&self.theme.grid_minor));
+        self.context.set_line_width(1.0 / self.zoom);
+        self.context.begin_path();
+        let mut offset = ((min_x + min_y) / spacing).floor() * spacing;
+        while offset <= max_x + max_y {
+            // Lines where x + y = offset, spanning the visible rectangle
+            self.context.move_to(offset - min_y, min_y);
+            self.context.line_to(offset - max_y, max_y);
+            offset += spacing;
+        }
+        self.context.stroke();
+        self.add_draw_calls(2);
+        self.context.restore();
+    }
+    
+    fn render_hex_grid(&self, map_width: f64, map_height: f64, grid_size: f64) {
+        // Pointy-top hexes grid_size wide, with every other row shifted by half a hex
+        let radius = grid_size / 3f64.sqrt();
+        let row_height = radius * 1.5;
+        
+        // Fade out like the square grid's fine lines as hexes shrink on screen
+        let alpha = ((grid_size * self.zoom - 8.0) / 12.0).clamp(0.0, 1.0);
+        if alpha <= 0.0 || self.quality == 0 {
+            return;
+        }
+        
+        // Only trace hexes in the visible part of the map
+        let (min_x, min_y, max_x, max_y) = self.visible_world_rect();
+        let first_row = (min_y.max(0.0) / row_height).floor() as i64 - 1;
+        let last_row = (max_y.min(map_height) / row_height).ceil() as i64 + 1;
+        let first_column = (min_x.max(0.0) / grid_size).floor() as i64 - 1;
+        let last_column = (max_x.min(map_width) / grid_size).ceil() as i64 + 1;
+        
+        self.context.save();
+        self.context.begin_path();
+        self.context.rect(0.0, 0.0, map_width, map_height);
+        self.context.clip();
+        
+        self.context.set_global_alpha(alpha);
+        self.context.set_stroke_style(&JsValue::from_str(&self.theme.grid_minor));
+        self.context.set_line_width(1.0 / self.zoom);
+        self.context.begin_path();
+        for row in first_row..=last_row {
+            let center_y = row as f64 * row_height;
+            let shift = if row.rem_euclid(2) == 1 { grid_size / 2.0 } else { 0.0 };
+            for column in first_column..=last_column {
+                let center_x = column as f64 * grid_size + shift;
+                for corner in 0..6 {
+                    let angle = PI / 6.0 + corner as f64 * PI / 3.0;
+                    let (x, y) = (center_x + radius * angle.cos(), center_y + radius * angle.sin());
+                    if corner == 0 {
+                        self.context.move_to(x, y);
+                    } else {
+                        self.context.line_to(x, y);
+                    }
+                }
+                self.context.close_path();
+            }
+        }
+        self.context.stroke();
+        self.add_draw_calls(1);
+        
+        self.context.restore();
+    }
+    
+    fn stroke_grid_lines(&self, map_width: f64, map_height: f64, step: f64) {
+        self.context.begin_path();
+        
+        // Vertical lines
+        let mut x = 0.0;
+        while x <= map_width {
+            self.context.move_to(x, 0.0);
+            self.context.line_to(x, map_height);
+            x += step;
+        }
+        
+        // Horizontal lines
+        let mut y = 0.0;
+        while y <= map_height {
+            self.context.move_to(0.0, y);
+            self.context.line_to(map_width, y);
+            y += step;
+        }
+        
+        self.context.stroke();
+    }
+    
+    fn render_troop_ids(&self, game_state: &GameState) {
+        // Drawn in screen space so the IDs stay readable at any zoom
+        let camera = self.camera();
+        let view = camera.visible_world_rect();
+        
+        self.context.save();
+        self.context.set_font("10px monospace");
+        self.context.set_text_align("center");
+        self.context.set_fill_style(&JsValue::from_str("#ffff00"));
+        
+        for troop in &game_state.troops {
+            let (x, y) = troop.position;
+            if !in_view(view, (x, y), 20.0) || !self.is_troop_visible(game_state, troop) {
+                continue;
+            }
+            
+            let (screen_x, screen_y) = camera.world_to_screen(x, y);
+            self.context.fill_text(&troop.id.to_string(), screen_x, screen_y - troop.size() * self.zoom - 8.0).unwrap();
+        }
+        
+        self.context.restore();
+    }
+    
+    fn render_troop_names(&self, game_state: &GameState) {
+        // Drawn in screen space like the IDs, and above them when both are shown
+        let offset = if self.show_dev_tools && self.show_troop_ids { 20.0 } else { 8.0 };
+        let camera = self.camera();
+        let view = camera.visible_world_rect();
+        
+        self.context.save();
+        self.context.set_font("12px Arial");
+        self.context.set_text_align("center");
+        self.context.set_fill_style(&JsValue::from_str("#ffffff"));
+        
+        for troop in &game_state.troops {
+            let name = match &troop.name {
+                Some(name) => name,
+                None => continue,
+            };
+            
+            let (x, y) = troop.position;
+            if !in_view(view, (x, y), 50.0) || !self.is_troop_visible(game_state, troop) {
+                continue;
+            }
+            
+            let (screen_x, screen_y) = camera.world_to_screen(x, y);
+            self.context.fill_text(name, screen_x, screen_y - troop.size() * self.zoom - offset).unwrap();
+        }
+        
+        self.context.restore();
+    }
+    
+    fn render_grid_labels(&self, game_state: &GameState, grid_size: f64) {
+        let (map_width, map_height) = game_state.map_size;
+        
+        // Skip grid lines so labels stay at least 50 screen pixels apart
+        let min_label_spacing = 50.0;
+        let step = grid_size * (min_label_spacing / (grid_size * self.zoom)).ceil().max(1.0);
+        
+        // Pin labels to the map's top and left edges, or the edge of the view once those scroll away
+        let camera = self.camera();
+        let (min_x, min_y, _, _) = camera.visible_world_rect();
+        let top = min_y.max(0.0);
+        let left = min_x.max(0.0);
+        
+        self.context.save();
+        self.context.set_font("11px Arial");
+        self.context.set_fill_style(&JsValue::from_str("#aaaaaa"));
+        
+        // Labels along the top edge
+        let mut x = 0.0;
+        while x <= map_width {
+            let (screen_x, screen_y) = camera.world_to_screen(x, top);
+            self.context.fill_text(&format!("{:.0}", x), screen_x + 2.0, screen_y + 12.0).unwrap();
+            x += step;
+        }
+        
+        // Labels along the left edge
+        let mut y = step;
+        while y <= map_height {
+            let (screen_x, screen_y) = camera.world_to_screen(left, y);
+            self.context.fill_text(&format!("{:.0}", y), screen_x + 2.0, screen_y - 2.0).unwrap();
+            y += step;
+        }
+        
+        self.context.restore();
+    }
+    
+    fn render_waypoints(&self) {
+        // Only show the queue while its troops are the ones selected
+        if self.waypoints.is_empty() || !same_troop_set(&self.waypoint_troops, &self.selected_troops) {
+            return;
+        }
+        
+        self.context.save();
+        
+        // Apply camera transform
+        self.apply_camera_transform(&self.context);
+        
+        // Connect the waypoints in order
+        self.context.set_stroke_style(&JsValue::from_str("rgba(0, 255, 0, 0.6)"));
+        self.context.set_line_width(1.0 / self.zoom);
+        self.context.begin_path();
+        for (i, &(x, y)) in self.waypoints.iter().enumerate() {
+            if i == 0 {
+                self.context.move_to(x, y);
+            } else {
+                self.context.line_to(x, y);
+            }
+        }
+        self.context.stroke();
+        
+        // Draw a dot at each waypoint
+        self.context.set_fill_style(&JsValue::from_str("#00ff00"));
+        self.context.begin_path();
+        for &(x, y) in &self.waypoints {
+            self.context.move_to(x + 4.0 / self.zoom, y);
+            self.context.arc(x, y, 4.0 / self.zoom, 0.0, 2.0 * PI).unwrap();
+        }
+        self.context.fill();
+        
+        self.context.restore();
+    }
+    
+    #[wasm_bindgen]
+    pub fn set_rally_point(&mut self, world_x: f64, world_y: f64) {
+        // Display only; the server decides where newly spawned troops go
+        self.rally_point = Some((world_x, world_y));
+    }
+    
+    #[wasm_bindgen]
+    pub fn clear_rally_point(&mut self) {
+        self.rally_point = None;
+    }
+    
+    fn render_rally_point(&self, game_state: &GameState) {
+        let (flag_x, flag_y) = match self.rally_point {
+            Some(rally_point) => rally_point,
+            None => return,
+        };
+        let base = game_state.players.iter().find(|p| Some(p.id) == self.player_id);
+        
+        self.context.save();
+        
+        // Apply camera transform
+        self.apply_camera_transform(&self.context);
+        
+        // Faint dashed line from the local player's base to the flag
+        if let Some(base) = base {
+            let dash = Array::new();
+            dash.push(&JsValue::from_f64(6.0));
+            dash.push(&JsValue::from_f64(4.0));
+            self.context.set_line_dash(&dash).unwrap();
+            self.context.set_stroke_style(&JsValue::from_str("rgba(255, 255, 255, 0.3)"));
+            self.context.set_line_width(1.0);
+            self.context.begin_path();
+            self.context.move_to(base.position.0, base.position.1);
+            self.context.line_to(flag_x, flag_y);
+            self.context.stroke();
+            self.context.set_line_dash(&Array::new()).unwrap();
+        }
+        
+        // Flag pole
+        self.context.set_stroke_style(&JsValue::from_str("#ffffff"));
+        self.context.set_line_width(1.5);
+        self.context.begin_path();
+        self.context.move_to(flag_x, flag_y);
+        self.context.line_to(flag_x, flag_y - 20.0);
+        self.context.stroke();
+        
+        // Banner in the player's color
+        let (r, g, b) = self.apply_colorblind(base.map_or((255, 255, 255), |p| p.color));
+        self.context.set_fill_style(&JsValue::from_str(&format!("rgb({}, {}, {})", r, g, b)));
+        self.context.begin_path();
+        self.context.move_to(flag_x, flag_y - 20.0);
+        self.context.line_to(flag_x + 12.0, flag_y - 16.0);
+        self.context.line_to(flag_x, flag_y - 12.0);
+        self.context.close_path();
+        self.context.fill();
+        
+        self.context.restore();
+    }
+    
+    fn render_players(&self, game_state: &GameState) {
+        let view = self.visible_world_rect();
+        
+        self.context.save();
+        
+        // Apply camera transform
+        self.apply_camera_transform(&self.context);
+        
+        let base_size = 40.0;
+        
+        // Draw each player's base as a hollow square
+        for player in &game_state.players {
+            let (x, y) = player.position;
+            if !in_view(view, (x, y), base_size) {
+                continue;
+            }
+            
+            let (r, g, b) = self.apply_colorblind(player.color);
+            self.context.set_stroke_style(&JsValue::from_str(&format!("rgb({}, {}, {})", r, g, b)));
+            self.context.set_line_width(3.0);
+            self.context.stroke_rect(x - base_size/2.0, y - base_size/2.0, base_size, base_size);
+            
+            // Highlight the local player's base
+            if self.player_id == Some(player.id) {
+                let highlight_size = base_size + 10.0;
+                self.context.set_stroke_style(&JsValue::from_str("#ffffff"));
+                self.context.set_line_width(1.0);
+                self.context.stroke_rect(x - highlight_size/2.0, y - highlight_size/2.0, highlight_size, highlight_size);
+            }
+        }
+        
+        self.context.restore();
+    }
+    
+    fn render_troops(&self, game_state: &GameState) {
+        let view = self.visible_world_rect();
+        
+        self.context.save();
+        
+        // Apply camera transform
+        self.apply_camera_transform(&self.context);
+        
+        // Collect the troops that will actually be drawn
+        let mut troops = Vec::new();
+        for troop in &game_state.troops {
+            let (x, y) = troop.position;
+            
+            // Skip off-screen troops, with a margin for the health bar or range indicator
+            let margin = if self.show_ranges && self.selected_troops.contains(&troop.id) {
+                troop.max_range.or(troop.attack_range).unwrap_or(0.0).max(troop.size() * 2.0)
+            } else {
+                troop.size() * 2.0
+            };
+            if !in_view(view, (x, y), margin) {
+                continue;
+            }
+            
+            if !self.is_troop_visible(game_state, troop) {
+                continue;
+            }
+            
+            troops.push(troop);
+        }
+        
+        self.update_render_stats(|stats| {
+            stats.troops_drawn = troops.len();
+            stats.troops_culled = game_state.troops.len() - troops.len();
+        });
+        
+        // Troops are drawn in passes rather than one save/translate/restore per troop, so
+        // styles are set once per pass and shapes share one fill per (shape, color) batch.
+        // This takes the shape fills from one per troop down to one per batch.
+        
+        // Draw paths from selected troops to their destinations
+        if self.show_paths && !self.selected_troops.is_empty() {
+            self.render_paths(game_state, &troops);
+        }
+        
+        // Draw lines from attacking troops to their targets
+        if self.show_attack_lines {
+            self.render_attack_lines(game_state, &troops);
+        }
+        
+        // Draw range, selection and friend-or-foe indicators beneath the troops
+        for troop in &troops {
+            let (x, y) = troop.position;
+            let size = troop.size();
+            
+            // Draw attack range for selected ranged troops
+            if self.show_ranges && self.selected_troops.contains(&troop.id) {
+                self.render_range_indicator(troop);
+            }
+            
+            // Draw selection indicator for selected troops
+            if self.selected_troops.contains(&troop.id) {
+                self.context.set_stroke_style(&JsValue::from_str("#00ff00"));
+                self.context.set_line_width(2.0);
+                
+                // Draw selection circle
+                self.context.begin_path();
+                self.context.arc(x, y, size * 0.8, 0.0, 2.0 * PI).unwrap();
+                self.context.stroke();
+                self.add_draw_calls(1);
+            }
+            
+            // Draw friend-or-foe outline
+            if self.iff_enabled {
+                if let Some(player_id) = self.player_id {
+                    let iff_color = if troop.player_id == player_id { "#00ffff" } else { "#ff0000" };
+                    self.context.set_stroke_style(&JsValue::from_str(iff_color));
+                    self.context.set_line_width(1.0);
+                    
+                    self.context.begin_path();
+                    self.context.arc(x, y, size * 0.65, 0.0, 2.0 * PI).unwrap();
+                    self.context.stroke();
+                    self.add_draw_calls(1);
+                }
+            }
+        }
+        
+        // Draw a pulsing red halo under friendly troops that are about to die
+        if self.low_health_warning {
+            if let Some(player_id) = self.player_id {
+                let pulse = 0.5 + 0.5 * (now() / 200.0).sin();
+                self.context.set_fill_style(&JsValue::from_str(&format!("rgba(255, 0, 0, {})", 0.15 + 0.35 * pulse)));
+                self.context.begin_path();
+                for troop in troops.iter().filter(|t| t.player_id == player_id && t.health_fraction() < self.low_health_warning_threshold) {
+                    let (x, y) = troop.position;
+                    let radius = troop.size() * (1.0 + 0.4 * pulse);
+                    self.context.move_to(x + radius, y);
+                    self.context.arc(x, y, radius, 0.0, 2.0 * PI).unwrap();
+                }
+                self.context.fill();
+                self.add_draw_calls(1);
+            }
+        }
+        
+        // Draw a pulsing ring around troops highlighted from JavaScript
+        if !self.highlighted.is_empty() {
+            let pulse = 0.5 + 0.5 * (now() / 150.0).sin();
+            self.context.set_stroke_style(&JsValue::from_str(&format!("rgba(255, 215, 0, {})", 0.4 + 0.6 * pulse)));
+            self.context.set_line_width(2.0);
+            self.context.begin_path();
+            for troop in troops.iter().filter(|t| self.highlighted.contains(&t.id)) {
+                let (x, y) = troop.position;
+                let radius = troop.size() * (1.0 + 0.3 * pulse);
+                self.context.move_to(x + radius, y);
+                self.context.arc(x, y, radius, 0.0, 2.0 * PI).unwrap();
+            }
+            self.context.stroke();
+            self.add_draw_calls(1);
+        }
+        
+        // Ease triangle facings toward their direction before drawing them
+        // Turning is eased per frame, so only the main view advances it
+        if !self.drawing_secondary_view {
+            self.update_troop_angles(game_state, &troops);
+        }
+        
+        // Group troop shapes by shape and color
+        let mut batches: BTreeMap<(&str, String), Vec<&Troop>> = BTreeMap::new();
+        for troop in &troops {
+            let mut base_color = troop.color;
+            
+            // Tint wounded troops toward red, in 10% steps to keep batches few
+            if self.health_shading {
+                let damage = ((1.0 - troop.health_fraction()) * 10.0).round() / 10.0;
+                base_color = blend_color(base_color, (255, 0, 0), damage);
+            }
+            
+            let (mut r, mut g, mut b) = self.apply_colorblind(base_color);
+            
+            // Brighten selected troops toward white, after the colorblind remap so it stays visible
+            if self.selected_brightness > 0.0 && self.selected_troops.contains(&troop.id) {
+                (r, g, b) = blend_color((r, g, b), (255, 255, 255), self.selected_brightness);
+            }
+            
+            let color = if troop.is_stealthed {
+                // Stealthed troops are drawn translucent
+                format!("rgba({}, {}, {}, 0.4)", r, g, b)
+            } else {
+                format!("rgb({}, {}, {})", r, g, b)
+            };
+            // Low quality draws every troop as a cheap dot
+            let shape = if self.quality == 0 { "dot" } else { troop.shape.as_str() };
+            batches.entry((shape, color)).or_default().push(troop);
+        }
+        
+        // Draw each batch as a single path with one fill, or one stroke when outlined
+        self.context.set_line_width(1.5);
+        for ((shape, color), group) in &batches {
+            self.context.begin_path();
+            for troop in group {
+                self.trace_troop_shape(shape, troop);
+            }
+            match self.render_style {
+                RenderStyle::Filled => {
+                    self.context.set_fill_style(&JsValue::from_str(color));
+                    self.context.fill();
+                },
+                RenderStyle::Outlined => {
+                    self.context.set_stroke_style(&JsValue::from_str(color));
+                    self.context.stroke();
+                },
+            }
+        }
+        self.add_draw_calls(batches.len());
+        
+        // Draw a shimmering dashed outline around stealthed troops
+        if troops.iter().any(|t| t.is_stealthed) {
+            self.context.save();
+            self.context.set_stroke_style(&JsValue::from_str("rgba(200, 230, 255, 0.8)"));
+            self.context.set_line_width(1.0);
+            
+            let dash = Array::new();
+            dash.push(&JsValue::from_f64(3.0));
+            dash.push(&JsValue::from_f64(3.0));
+            self.context.set_line_dash(&dash).unwrap();
+            self.context.set_line_dash_offset(-(now() / 50.0) % 6.0);
+            
+            self.context.begin_path();
+            for troop in troops.iter().filter(|t| t.is_stealthed) {
+                self.trace_troop_shape(&troop.shape, troop);
+            }
+            self.context.stroke();
+            self.add_draw_calls(1);
+            self.context.restore();
+        }
+        
+        // Draw a flash ring around troops that are currently attacking
+        self.context.set_stroke_style(&JsValue::from_str("rgba(255, 255, 0, 0.8)"));
+        self.context.set_line_width(1.5);
+        self.context.begin_path();
+        for troop in troops.iter().filter(|t| t.is_attacking) {
+            let (x, y) = troop.position;
+            let radius = troop.size() * 0.9;
+            self.context.move_to(x + radius, y);
+            self.context.arc(x, y, radius, 0.0, 2.0 * PI).unwrap();
+        }
+        self.context.stroke();
+        self.add_draw_calls(1);
+        
+        // Draw direction indicators as a single path (triangles already show their facing)
+        if self.quality > 0 {
+            self.context.set_stroke_style(&JsValue::from_str(&self.direction_line_color));
+            self.context.set_line_width(1.0);
+            self.context.begin_path();
+            for troop in troops.iter().filter(|t| t.shape != "triangle") {
+                let (x, y) = troop.position;
+                let (dx, dy) = troop.direction;
+                let length = self.direction_line_length * troop.size() / 10.0;
+                self.context.move_to(x, y);
+                self.context.line_to(x + dx * length, y + dy * length);
+            }
+            self.context.stroke();
+            self.add_draw_calls(1);
+        }
+        
+        // Draw health bars (health shading replaces them)
+        let bar_troops: Vec<&Troop> = troops.iter()
+            .copied()
+            .filter(|t| match self.health_bar_mode {
+                HealthBarMode::Always => true,
+                HealthBarMode::DamagedOrSelected => {
+                    t.health_fraction() < 1.0 || self.selected_troops.contains(&t.id)
+                },
+                HealthBarMode::Never => false,
+            })
+            .collect();
+        if self.quality > 0 && !self.health_shading && !bar_troops.is_empty() {
+            let health_height = 2.0;
+            
+            self.context.set_fill_style(&JsValue::from_str("#ff0000"));
+            for troop in &bar_troops {
+                let (x, y) = troop.position;
+                let health_width = troop.size() * 1.5;
+                let health_y = y - troop.size() - 5.0;
+                self.context.fill_rect(x - health_width/2.0, health_y, health_width, health_height);
+            }
+            
+            self.context.set_fill_style(&JsValue::from_str("#00ff00"));
+            for troop in &bar_troops {
+                let (x, y) = troop.position;
+                let health_width = troop.size() * 1.5;
+                let health_y = y - troop.size() - 5.0;
+                let health_percent = troop.health_fraction();
+                self.context.fill_rect(x - health_width/2.0, health_y, health_width * health_percent, health_height);
+            }
+            self.add_draw_calls(bar_troops.len() * 2);
+        }
+        
+        self.render_rank_badges(&troops);
+        
+        if self.show_stack_counts {
+            self.render_stack_counts(&troops);
+        }
+        
+        self.context.restore();
+    }
+    
+    fn render_stack_counts(&self, troops: &[&Troop]) {
+        // Troops closer than a few screen pixels are indistinguishable, so group them
+        let radius = 6.0 / self.zoom;
+        let mut index = TroopIndex::new(radius);
+        index.rebuild_from(troops.iter().copied());
+        
+        // Greedily gather unclaimed troops of the same player around each troop
+        let mut claimed = vec![false; troops.len()];
+        let mut stacks = Vec::new();
+        for (i, troop) in troops.iter().enumerate() {
+            if claimed[i] {
+                continue;
+            }
             
-            // Only select if the box is large enough (to avoid accidental selections)
-            let selection_size = (max_x - min_x) * (max_y - min_y);
-            if selection_size > 25.0 {  // Minimum selection area
-                self.select_troops_in_box(min_x, min_y, max_x, max_y);
+            let (x, y) = troop.position;
+            let members: Vec<usize> = index.query(x, y, radius).into_iter()
+                .filter(|&j| !claimed[j] && troops[j].player_id == troop.player_id)
+                .filter(|&j| {
+                    let (other_x, other_y) = troops[j].position;
+                    (other_x - x).powi(2) + (other_y - y).powi(2) <= radius * radius
+                })
+                .collect();
+            if members.len() < 3 {
+                continue;
             }
             
-            self.selection_start = None;
-            self.selection_end = None;
+            let count = members.len() as f64;
+            let center_x = members.iter().map(|&j| troops[j].position.0).sum::<f64>() / count;
+            let center_y = members.iter().map(|&j| troops[j].position.1).sum::<f64>() / count;
+            for &j in &members {
+                claimed[j] = true;
+            }
+            stacks.push((center_x, center_y, members.len()));
         }
         
-        self.is_dragging = false;
+        if stacks.is_empty() {
+            return;
+        }
+        
+        // Draw a screen-stable count badge over each stack
+        let unit = 1.0 / self.zoom;
+        self.context.save();
+        self.context.set_font(&format!("{}px Arial", 11.0 * unit));
+        self.context.set_text_align("center");
+        self.context.set_text_baseline("middle");
+        for (x, y, count) in &stacks {
+            self.context.set_fill_style(&JsValue::from_str("rgba(0, 0, 0, 0.75)"));
+            self.context.begin_path();
+            self.context.arc(*x, *y, 9.0 * unit, 0.0, 2.0 * PI).unwrap();
+            self.context.fill();
+            
+            self.context.set_fill_style(&JsValue::from_str("#ffffff"));
+            self.context.fill_text(&count.to_string(), *x, *y).unwrap();
+        }
+        self.add_draw_calls(stacks.len() * 2);
+        self.context.restore();
     }
     
-    fn select_troops_in_box(&mut self, min_x: f64, min_y: f64, max_x: f64, max_y: f64) {
-        if let Some(game_state) = &self.game_state {
-            if let Some(player_id) = self.player_id {
-                // Clear previous selection
-                self.selected_troops.clear();
-                
-                // Select all player's troops in the box
-                for troop in &game_state.troops {
-                    if troop.player_id == player_id {
-                        let (x, y) = troop.position;
-                        if x >= min_x && x <= max_x && y >= min_y && y <= max_y {
-                            self.selected_troops.push(troop.id);
-                        }
-                    }
-                }
+    fn render_rank_badges(&self, troops: &[&Troop]) {
+        // Badges stay the same size on screen so veterans are recognizable when zoomed out
+        let unit = 1.0 / self.zoom;
+        let chevron_width = 6.0 * unit;
+        let chevron_height = 3.0 * unit;
+        let spacing = 3.0 * unit;
+        
+        self.context.save();
+        self.context.set_stroke_style(&JsValue::from_str("#ffd700"));
+        self.context.set_line_width(1.5 * unit);
+        self.context.begin_path();
+        for troop in troops {
+            let rank = troop.rank.unwrap_or(0).min(3);
+            if rank == 0 {
+                continue;
+            }
+            
+            // Stack chevrons upward beside the troop's top-right corner
+            let (x, y) = troop.position;
+            let left = x + troop.size() + 2.0 * unit;
+            let bottom = y - troop.size() / 2.0;
+            for i in 0..rank {
+                let tip_y = bottom - i as f64 * spacing - chevron_height;
+                self.context.move_to(left, tip_y + chevron_height);
+                self.context.line_to(left + chevron_width / 2.0, tip_y);
+                self.context.line_to(left + chevron_width, tip_y + chevron_height);
             }
         }
+        self.context.stroke();
+        self.add_draw_calls(1);
+        self.context.restore();
     }
     
-    #[wasm_bindgen]
-    pub fn handle_wheel(&mut self, delta_y: f64) {
-        // Zoom in/out with mouse wheel
-        let zoom_factor = if delta_y > 0.0 { 0.9 } else { 1.1 };
-        self.zoom *= zoom_factor;
+    fn render_attack_lines(&self, game_state: &GameState, troops: &[&Troop]) {
+        let targets: HashMap<u32, &Troop> = game_state.troops.iter()
+            .map(|t| (t.id, t))
+            .collect();
+        
+        self.context.save();
+        self.context.set_stroke_style(&JsValue::from_str("rgba(255, 80, 40, 0.7)"));
+        self.context.set_line_width(1.0);
         
-        // Clamp zoom level
-        self.zoom = self.zoom.max(0.2).min(5.0);
+        self.context.begin_path();
+        for troop in troops.iter().filter(|t| t.is_attacking) {
+            // Hidden targets stay hidden, so only draw to troops the player can see
+            let target = match troop.target.and_then(|id| targets.get(&id)) {
+                Some(target) if self.is_troop_visible(game_state, target) => target,
+                _ => continue,
+            };
+            
+            self.context.move_to(troop.position.0, troop.position.1);
+            self.context.line_to(target.position.0, target.position.1);
+        }
+        self.context.stroke();
+        self.add_draw_calls(1);
+        
+        self.context.restore();
     }
     
-    #[wasm_bindgen]
-    pub fn handle_click(&mut self, event: MouseEvent) -> Option<JsValue> {
-        if self.is_dragging || self.player_id.is_none() || self.game_state.is_none() {
-            return None;
-        }
+    fn render_paths(&self, game_state: &GameState, troops: &[&Troop]) {
+        let positions: HashMap<u32, (f64, f64)> = game_state.troops.iter()
+            .map(|t| (t.id, t.position))
+            .collect();
         
-        let rect = self.canvas.get_bounding_client_rect();
-        let canvas_x = event.client_x() as f64 - rect.left();
-        let canvas_y = event.client_y() as f64 - rect.top();
+        self.context.save();
+        self.context.set_stroke_style(&JsValue::from_str("rgba(255, 255, 255, 0.4)"));
+        self.context.set_line_width(1.0);
         
-        // Convert canvas coordinates to world coordinates
-        let world_x = canvas_x / self.zoom + self.camera_x;
-        let world_y = canvas_y / self.zoom + self.camera_y;
+        let dash = Array::new();
+        dash.push(&JsValue::from_f64(4.0));
+        dash.push(&JsValue::from_f64(4.0));
+        self.context.set_line_dash(&dash).unwrap();
         
-        // Find the player's position
-        let game_state = self.game_state.as_ref().unwrap();
-        let player_id = self.player_id.unwrap();
+        self.context.begin_path();
+        for troop in troops.iter().filter(|t| self.selected_troops.contains(&t.id)) {
+            // Prefer an explicit destination, otherwise follow the target troop
+            let destination = troop.target_position
+                .or_else(|| troop.target.and_then(|id| positions.get(&id).copied()));
+            
+            if let Some((target_x, target_y)) = destination {
+                self.context.move_to(troop.position.0, troop.position.1);
+                self.context.line_to(target_x, target_y);
+            }
+        }
+        self.context.stroke();
+        self.add_draw_calls(1);
         
-        let player_position = game_state.players.iter()
-            .find(|p| p.id == player_id)
-            .map(|p| p.position);
+        self.context.restore();
+    }
+    
+    fn update_troop_angles(&self, game_state: &GameState, troops: &[&Troop]) {
+        let mut angles = self.troop_angles.borrow_mut();
         
-        if let Some(position) = player_position {
-            // Calculate direction from player to click point
-            let dx = world_x - position.0;
-            let dy = world_y - position.1;
+        // Forget troops that no longer exist
+        let alive: HashSet<u32> = game_state.troops.iter().map(|t| t.id).collect();
+        angles.retain(|id, _| alive.contains(id));
+        
+        // Frame-rate independent easing with a time constant of about 80ms
+        let frame_time = self.frame_times.back().copied().unwrap_or(16.6);
+        let blend = 1.0 - (-frame_time / 80.0).exp();
+        
+        for troop in troops.iter().filter(|t| t.shape == "triangle") {
+            // Stationary troops keep facing the way they were
+            if troop.direction == (0.0, 0.0) {
+                continue;
+            }
             
-            // Create spawn data (unit type will be added by JavaScript)
-            let spawn_data = Object::new();
-            Reflect::set(&spawn_data, &"position".into(), &array_from_tuple(position))?;
-            Reflect::set(&spawn_data, &"direction".into(), &array_from_tuple((dx, dy)))?;
-            Reflect::set(&spawn_data, &"count".into(), &JsValue::from_f64(15.0))?;
+            let target = facing_angle(troop.direction);
+            let angle = angles.entry(troop.id).or_insert(target);
             
-            return Some(spawn_data.into());
+            // Turn along the shortest arc
+            let difference = (target - *angle + PI).rem_euclid(2.0 * PI) - PI;
+            *angle += difference * blend;
         }
+    }
+    
+    fn troop_angle(&self, troop: &Troop) -> f64 {
+        self.troop_angles.borrow().get(&troop.id).copied()
+            .unwrap_or_else(|| facing_angle(troop.direction))
+    }
+    
+    fn troop_draw_position(&self, troop: &Troop) -> (f64, f64) {
+        let (x, y) = troop.position;
         
-        None
+        // Troops only a few pixels across pile onto one dot when zoomed out, so nudge each
+        // by up to 2 screen pixels. The offset comes from the ID so it doesn't shimmer.
+        if self.spread_crowds && troop.size() * self.zoom < 4.0 {
+            let (jitter_x, jitter_y) = id_jitter(troop.id);
+            let spread = 2.0 / self.zoom;
+            (x + jitter_x * spread, y + jitter_y * spread)
+        } else {
+            (x, y)
+        }
     }
     
-    #[wasm_bindgen]
-    pub fn handle_right_click(&mut self, event: MouseEvent) -> Option<JsValue> {
-        if self.player_id.is_none() || self.game_state.is_none() || self.selected_troops.is_empty() {
-            return None;
+    fn trace_troop_shape(&self, shape: &str, troop: &Troop) {
+        // Add the troop's outline to the current path without filling it
+        let center = self.troop_draw_position(troop);
+        let angle = if shape == "triangle" { self.troop_angle(troop) } else { 0.0 };
+        
+        match shape_path(shape, troop, center, angle) {
+            ShapePath::Circle { center: (x, y), radius } => {
+                self.context.move_to(x + radius, y);
+                self.context.arc(x, y, radius, 0.0, 2.0 * PI).unwrap();
+            },
+            ShapePath::Rect { origin: (x, y), size: (width, height) } => {
+                self.context.rect(x, y, width, height);
+            },
+            ShapePath::Triangle(points) => self.trace_polygon(&points),
+            ShapePath::Quad(points) => self.trace_polygon(&points),
+        }
+    }
+    
+    fn trace_polygon(&self, points: &[(f64, f64)]) {
+        let (first_x, first_y) = points[0];
+        self.context.move_to(first_x, first_y);
+        for &(x, y) in &points[1..] {
+            self.context.line_to(x, y);
         }
+        self.context.close_path();
+    }
+    
+    fn render_range_indicator(&self, troop: &Troop) {
+        let (x, y) = troop.position;
+        let (r, g, b) = self.apply_colorblind(troop.color);
         
-        let rect = self.canvas.get_bounding_client_rect();
-        let canvas_x = event.client_x() as f64 - rect.left();
-        let canvas_y = event.client_y() as f64 - rect.top();
+        self.context.save();
+        self.context.translate(x, y).unwrap();
+        self.context.set_fill_style(&JsValue::from_str(&format!("rgba({}, {}, {}, 0.1)", r, g, b)));
+        self.context.set_stroke_style(&JsValue::from_str(&format!("rgba({}, {}, {}, 0.4)", r, g, b)));
+        self.context.set_line_width(1.0);
         
-        // Convert canvas coordinates to world coordinates
-        let world_x = canvas_x / self.zoom + self.camera_x;
-        let world_y = canvas_y / self.zoom + self.camera_y;
+        match (drawable_range(troop.min_range), drawable_range(troop.max_range), drawable_range(troop.attack_range)) {
+            (Some(min_range), Some(max_range), _) => {
+                // Annulus between minimum and maximum range
+                self.context.begin_path();
+                self.context.arc(0.0, 0.0, max_range, 0.0, 2.0 * PI).unwrap();
+                self.context.move_to(min_range, 0.0);
+                self.context.arc_with_anticlockwise(0.0, 0.0, min_range, 0.0, 2.0 * PI, true).unwrap();
+                self.context.fill();
+                
+                self.context.begin_path();
+                self.context.arc(0.0, 0.0, max_range, 0.0, 2.0 * PI).unwrap();
+                self.context.stroke();
+                
+                self.context.begin_path();
+                self.context.arc(0.0, 0.0, min_range, 0.0, 2.0 * PI).unwrap();
+                self.context.stroke();
+                self.add_draw_calls(3);
+            },
+            (_, _, Some(attack_range)) => {
+                self.context.begin_path();
+                self.context.arc(0.0, 0.0, attack_range, 0.0, 2.0 * PI).unwrap();
+                self.context.fill();
+                self.context.stroke();
+                self.add_draw_calls(2);
+            },
+            _ => {}
+        }
         
-        // Create move data
-        let move_data = Object::new();
-        Reflect::set(&move_data, &"target_position".into(), &array_from_tuple((world_x, world_y)))?;
+        self.context.restore();
+    }
+    
+    fn render_projectiles(&self, game_state: &GameState) {
+        let view = self.visible_world_rect();
         
-        // Add selected troop IDs
-        let selected_array = Array::new();
-        for &id in &self.selected_troops {
-            selected_array.push(&JsValue::from_f64(id as f64));
+        self.context.save();
+        
+        // Apply camera transform
+        self.apply_camera_transform(&self.context);
+        
+        // Draw projectiles
+        for projectile in &game_state.projectiles {
+            let (x, mut y) = projectile.position;
+            let (dx, mut dy) = projectile.direction;
+            
+            // Ballistic projectiles are drawn lifted off the ground, pointing along the arc
+            if let Some(arc_height) = projectile.arc_height {
+                let (height, climb_rate) = projectile.arc(arc_height);
+                y -= height;
+                dy -= climb_rate / projectile.speed.max(1.0);
+            }
+            
+            // Trail covers roughly the last 100ms of travel
+            let trail_length = if self.draws_trails() { projectile.speed * 0.1 } else { 0.0 };
+            
+            // Skip off-screen projectiles, with a margin for the trail
+            if !in_view(view, (x, y), 10.0 + trail_length) {
+                self.update_render_stats(|stats| stats.projectiles_culled += 1);
+                continue;
+            }
+            
+            if !self.is_visible(game_state, projectile.player_id, projectile.position) {
+                self.update_render_stats(|stats| stats.projectiles_culled += 1);
+                continue;
+            }
+            
+            let (r, g, b) = self.apply_colorblind(projectile.color);
+            let color = format!("rgb({}, {}, {})", r, g, b);
+            
+            self.context.save();
+            self.context.translate(x, y).unwrap();
+            
+            // Draw arrow
+            self.context.set_fill_style(&JsValue::from_str(&color));
+            
+            // Rotate context to match arrow direction
+            self.context.rotate(facing_angle((dx, dy))).unwrap();
+            
+            // Draw fading trail behind the arrow
+            if self.draws_trails() {
+                self.render_projectile_trail((r, g, b), trail_length);
+            }
+            
+            // Draw arrow body
+            let arrow_length = 8.0;
+            let arrow_width = 2.0;
+            
+            // Arrow shaft
+            self.context.fill_rect(-arrow_length/2.0, -arrow_width/2.0, arrow_length, arrow_width);
+            
+            // Arrow head
+            self.context.begin_path();
+            self.context.move_to(arrow_length/2.0, 0.0);
+            self.context.line_to(arrow_length/2.0 - 4.0, -4.0);
+            self.context.line_to(arrow_length/2.0 - 4.0, 4.0);
+            self.context.close_path();
+            self.context.fill();
+            
+            self.context.restore();
+            
+            self.update_render_stats(|stats| {
+                stats.projectiles_drawn += 1;
+                stats.draw_calls += 2;
+            });
         }
-        Reflect::set(&move_data, &"troop_ids".into(), &selected_array)?;
         
-        return Some(move_data.into());
+        self.context.restore();
     }
     
-    #[wasm_bindgen]
-    pub fn get_selected_troops(&self) -> JsValue {
-        let selected_array = Array::new();
-        for &id in &self.selected_troops {
-            selected_array.push(&JsValue::from_f64(id as f64));
+    fn update_render_stats(&self, update: impl FnOnce(&mut RenderStats)) {
+        let mut stats = self.render_stats.get();
+        update(&mut stats);
+        self.render_stats.set(stats);
+    }
+    
+    fn add_draw_calls(&self, count: usize) {
+        self.update_render_stats(|stats| stats.draw_calls += count);
+    }
+    
+    fn render_projectile_trail(&self, color: (u8, u8, u8), trail_length: f64) {
+        let (r, g, b) = color;
+        let segments = 4;
+        let segment_length = trail_length / segments as f64;
+        
+        self.context.save();
+        self.context.set_line_width(1.5);
+        
+        // Draw segments back along the flight path with decreasing alpha
+        for i in 0..segments {
+            let alpha = 0.6 * (1.0 - i as f64 / segments as f64);
+            self.context.set_stroke_style(&JsValue::from_str(&format!("rgba({}, {}, {}, {})", r, g, b, alpha)));
+            self.context.begin_path();
+            self.context.move_to(-(i as f64) * segment_length, 0.0);
+            self.context.line_to(-((i + 1) as f64) * segment_length, 0.0);
+            self.context.stroke();
         }
-        selected_array.into()
+        self.add_draw_calls(segments);
+        
+        self.context.restore();
     }
     
-    #[wasm_bindgen]
-    pub fn render(&self) {
-        self.clear_canvas();
+    fn is_visible(&self, game_state: &GameState, owner_id: u32, position: (f64, f64)) -> bool {
+        let player_id = match self.player_id {
+            Some(player_id) if self.fog_enabled => player_id,
+            _ => return true,
+        };
         
-        if let Some(game_state) = &self.game_state {
-            self.render_grid(game_state);
-            self.render_troops(game_state);
-            self.render_projectiles(game_state);
-            self.render_selection_box();
-            
-            if self.show_dev_tools {
-                self.render_dev_tools();
+        // The player's own entities are always visible
+        if owner_id == player_id {
+            return true;
+        }
+        
+        // Enemy entities are only visible within vision range of a friendly troop
+        any_friendly_within(&game_state.troops, self.index_for(game_state), player_id, position, self.vision_radius)
+    }
+    
+    fn index_for(&self, game_state: &GameState) -> Option<&TroopIndex> {
+        // Only the latest and interpolated states are indexed; others, like the previous
+        // snapshot when diffing, have no index
+        let is = |state: &Option<GameState>| state.as_ref().is_some_and(|state| std::ptr::eq(state, game_state));
+        if is(&self.game_state) {
+            Some(&self.troop_index)
+        } else if is(&self.interpolated) {
+            Some(&self.interpolated_index)
+        } else {
+            None
+        }
+    }
+    
+    fn is_troop_visible(&self, game_state: &GameState, troop: &Troop) -> bool {
+        // Stealthed enemies stay hidden, even with fog disabled, until a friendly troop is
+        // within detection range. The server must still withhold their data from other clients.
+        if troop.is_stealthed {
+            if let Some(player_id) = self.player_id {
+                if troop.player_id != player_id {
+                    let detected = any_friendly_within(&game_state.troops, self.index_for(game_state), player_id, troop.position, self.detection_radius);
+                    if !detected {
+                        return false;
+                    }
+                }
             }
         }
+        
+        self.is_visible(game_state, troop.player_id, troop.position)
     }
     
-    fn render_selection_box(&self) {
-        if let (Some(start), Some(end)) = (self.selection_start, self.selection_end) {
-            self.context.save();
+    fn render_fog(&self, game_state: &GameState) {
+        let player_id = match self.player_id {
+            Some(player_id) => player_id,
+            None => return,
+        };
+        
+        // Keep the fog canvas the same size as the main canvas
+        let width = self.canvas.width();
+        let height = self.canvas.height();
+        if self.fog_canvas.width() != width || self.fog_canvas.height() != height {
+            self.fog_canvas.set_width(width);
+            self.fog_canvas.set_height(height);
+        }
+        
+        let context = &self.fog_context;
+        context.save();
+        
+        // Cover the whole view in fog
+        context.clear_rect(0.0, 0.0, width as f64, height as f64);
+        context.set_fill_style(&JsValue::from_str("rgba(0, 0, 0, 0.6)"));
+        context.fill_rect(0.0, 0.0, width as f64, height as f64);
+        
+        // Apply pixel ratio and camera transform
+        context.scale(self.pixel_ratio, self.pixel_ratio).unwrap();
+        self.apply_camera_transform(context);
+        
+        // Cut out the vision radius around each friendly troop
+        context.set_global_composite_operation("destination-out").unwrap();
+        context.set_fill_style(&JsValue::from_str("#000000"));
+        context.begin_path();
+        for troop in game_state.troops.iter().filter(|t| t.player_id == player_id) {
+            let (x, y) = troop.position;
+            context.move_to(x + self.vision_radius, y);
+            context.arc(x, y, self.vision_radius, 0.0, 2.0 * PI).unwrap();
+        }
+        context.fill();
+        
+        context.restore();
+        
+        // The main context is already scaled, so draw the full-resolution fog at CSS size
+        self.context.draw_image_with_html_canvas_element_and_dw_and_dh(
+            &self.fog_canvas,
+            0.0,
+            0.0,
+            self.view_width(),
+            self.view_height(),
+        ).unwrap();
+    }
+    
+    fn render_offscreen_indicators(&self, game_state: &GameState) {
+        let player_id = match self.player_id {
+            Some(player_id) => player_id,
+            None => return,
+        };
+        
+        let camera = self.camera();
+        let width = camera.view_width;
+        let height = camera.view_height;
+        let center_x = width / 2.0;
+        let center_y = height / 2.0;
+        let inset = 12.0;
+        
+        self.context.save();
+        
+        for troop in game_state.troops.iter().filter(|t| t.player_id == player_id) {
+            let (screen_x, screen_y) = camera.world_to_screen(troop.position.0, troop.position.1);
+            if screen_x >= 0.0 && screen_x <= width && screen_y >= 0.0 && screen_y <= height {
+                continue;
+            }
             
-            // Apply camera transform
-            self.context.translate(-self.camera_x * self.zoom, -self.camera_y * self.zoom).unwrap();
-            self.context.scale(self.zoom, self.zoom).unwrap();
+            // Direction from the center of the view toward the troop, in screen space
+            let dx = screen_x - center_x;
+            let dy = screen_y - center_y;
+            if dx == 0.0 && dy == 0.0 {
+                continue;
+            }
             
-            // Draw selection box
-            let min_x = start.0.min(end.0);
-            let max_x = start.0.max(end.0);
-            let min_y = start.1.min(end.1);
-            let max_y = start.1.max(end.1);
+            // Scale the direction until it hits the inset canvas edge
+            let scale_x = if dx != 0.0 { (center_x - inset) / dx.abs() } else { f64::INFINITY };
+            let scale_y = if dy != 0.0 { (center_y - inset) / dy.abs() } else { f64::INFINITY };
+            let scale = scale_x.min(scale_y);
+            let edge_x = center_x + dx * scale;
+            let edge_y = center_y + dy * scale;
             
-            self.context.set_stroke_style(&JsValue::from_str("rgba(0, 255, 0, 0.8)"));
-            self.context.set_line_width(1.0 / self.zoom);
-            self.context.set_fill_style(&JsValue::from_str("rgba(0, 255, 0, 0.2)"));
+            // Red for badly wounded troops, orange for troops in combat
+            let color = if troop.health_fraction() < 0.3 {
+                "#ff3333"
+            } else if troop.is_attacking {
+                "#ffaa00"
+            } else {
+                "rgba(255, 255, 255, 0.7)"
+            };
+            
+            // Draw an arrow pointing toward the troop
+            let angle = dy.atan2(dx);
+            let (tip_x, tip_y) = rotate_point((8.0, 0.0), angle);
+            let (left_x, left_y) = rotate_point((-4.0, -5.0), angle);
+            let (right_x, right_y) = rotate_point((-4.0, 5.0), angle);
             
+            self.context.set_fill_style(&JsValue::from_str(color));
             self.context.begin_path();
-            self.context.rect(min_x, min_y, max_x - min_x, max_y - min_y);
+            self.context.move_to(edge_x + tip_x, edge_y + tip_y);
+            self.context.line_to(edge_x + left_x, edge_y + left_y);
+            self.context.line_to(edge_x + right_x, edge_y + right_y);
+            self.context.close_path();
             self.context.fill();
-            self.context.stroke();
-            
-            self.context.restore();
         }
+        
+        self.context.restore();
     }
     
-    fn clear_canvas(&self) {
-        let width = self.canvas.width() as f64;
-        let height = self.canvas.height() as f64;
+    #[wasm_bindgen]
+    pub fn push_event(&mut self, text: String) {
+        self.event_log.push_back((text, now()));
+        
+        // Cap the log in case events arrive faster than they expire
+        while self.event_log.len() > 20 {
+            self.event_log.pop_front();
+        }
+    }
+    
+    fn render_event_log(&self) {
+        if self.event_log.is_empty() {
+            return;
+        }
+        
+        let now = now();
+        let x = self.view_width() - 10.0;
         
         self.context.save();
-        self.context.set_fill_style(&JsValue::from_str("#222222"));
-        self.context.fill_rect(0.0, 0.0, width, height);
+        self.context.set_font("14px Arial");
+        self.context.set_text_align("right");
+        
+        // Newest message at the top right, older ones below, fading out over their last second
+        for (i, (text, created_at)) in self.event_log.iter().rev().take(5).enumerate() {
+            let alpha = ((5000.0 - (now - created_at)) / 1000.0).clamp(0.0, 1.0);
+            let y = 24.0 + i as f64 * 20.0;
+            
+            self.context.set_fill_style(&JsValue::from_str(&format!("rgba(0, 0, 0, {})", 0.6 * alpha)));
+            self.context.fill_text(text, x + 1.0, y + 1.0).unwrap();
+            self.context.set_fill_style(&JsValue::from_str(&format!("rgba(255, 255, 255, {})", alpha)));
+            self.context.fill_text(text, x, y).unwrap();
+        }
+        
         self.context.restore();
     }
     
-    fn render_grid(&self, game_state: &GameState) {
-        let (map_width, map_height) = game_state.map_size;
-        let grid_size = 100.0;
+    fn render_hover_tooltip(&self, game_state: &GameState) {
+        let troop = match self.hovered_troop.and_then(|id| game_state.troops.iter().find(|t| t.id == id)) {
+            Some(troop) => troop,
+            None => return,
+        };
+        
+        let lines = [
+            capitalize(&troop.unit_type),
+            format!("Health: {:.0}/{:.0}", troop.health, troop.max_health.unwrap_or(100.0)),
+            format!("Attack: {:.1}", troop.attack),
+        ];
         
         self.context.save();
+        self.context.set_font("12px Arial");
         
-        // Apply camera transform
-        self.context.translate(-self.camera_x * self.zoom, -self.camera_y * self.zoom).unwrap();
-        self.context.scale(self.zoom, self.zoom).unwrap();
+        // Place the tooltip in screen space just below and right of the troop
+        let (screen_x, screen_y) = self.world_to_screen(troop.position.0, troop.position.1);
+        let (screen_x, screen_y) = (screen_x + 12.0, screen_y + 12.0);
+        let text_width = lines.iter()
+            .filter_map(|line| self.context.measure_text(line).ok())
+            .map(|metrics| metrics.width())
+            .fold(0.0, f64::max);
+        let box_width = text_width + 12.0;
+        let box_height = lines.len() as f64 * 16.0 + 6.0;
         
-        // Draw grid
-        self.context.set_stroke_style(&JsValue::from_str("#444444"));
+        // Draw tooltip box
+        self.context.set_fill_style(&JsValue::from_str("rgba(0, 0, 0, 0.8)"));
+        self.context.fill_rect(screen_x, screen_y, box_width, box_height);
+        self.context.set_stroke_style(&JsValue::from_str("#888888"));
         self.context.set_line_width(1.0);
+        self.context.stroke_rect(screen_x, screen_y, box_width, box_height);
         
-        // Vertical lines
-        for x in (0..=map_width as usize).step_by(grid_size as usize) {
-            self.context.begin_path();
-            self.context.move_to(x as f64, 0.0);
-            self.context.line_to(x as f64, map_height);
-            self.context.stroke();
+        self.context.set_fill_style(&JsValue::from_str("#ffffff"));
+        for (i, line) in lines.iter().enumerate() {
+            self.context.fill_text(line, screen_x + 6.0, screen_y + 16.0 + i as f64 * 16.0).unwrap();
         }
         
-        // Horizontal lines
-        for y in (0..=map_height as usize).step_by(grid_size as usize) {
-            self.context.begin_path();
-            self.context.move_to(0.0, y as f64);
-            self.context.line_to(map_width, y as f64);
-            self.context.stroke();
+        self.context.restore();
+    }
+    
+    fn render_selection_summary(&self, game_state: &GameState) {
+        // Tally selected troops by unit type
+        let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+        let mut total_health = 0.0;
+        let mut total_count = 0;
+        for troop in game_state.troops.iter().filter(|t| self.selected_troops.contains(&t.id)) {
+            *counts.entry(troop.unit_type.as_str()).or_insert(0) += 1;
+            total_health += troop.health;
+            total_count += 1;
+        }
+        
+        if total_count == 0 {
+            return;
         }
         
-        // Draw map border
-        self.context.set_stroke_style(&JsValue::from_str("#888888"));
-        self.context.set_line_width(2.0);
-        self.context.stroke_rect(0.0, 0.0, map_width, map_height);
+        let composition = counts.iter()
+            .map(|(unit_type, count)| format!("{} x{}", capitalize(unit_type), count))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let lines = [
+            format!("Selected: {}", total_count),
+            composition,
+            format!("Total health: {:.0}", total_health),
+        ];
+        
+        self.context.save();
+        self.context.set_font("14px Arial");
+        
+        // Size the panel to fit the widest line
+        let text_width = lines.iter()
+            .filter_map(|line| self.context.measure_text(line).ok())
+            .map(|metrics| metrics.width())
+            .fold(0.0, f64::max);
+        let panel_width = text_width + 20.0;
+        let panel_height = lines.len() as f64 * 20.0 + 10.0;
+        let panel_x = 10.0;
+        let panel_y = self.view_height() - 10.0 - panel_height;
+        
+        // Draw summary panel
+        self.context.set_fill_style(&JsValue::from_str("rgba(0, 0, 0, 0.7)"));
+        self.context.fill_rect(panel_x, panel_y, panel_width, panel_height);
+        
+        self.context.set_fill_style(&JsValue::from_str("#ffffff"));
+        for (i, line) in lines.iter().enumerate() {
+            self.context.fill_text(line, panel_x + 10.0, panel_y + 20.0 + i as f64 * 20.0).unwrap();
+        }
         
         self.context.restore();
     }
     
-    fn render_troops(&self, game_state: &GameState) {
+    fn render_scale_bar(&self, game_state: &GameState) {
+        // Pick a round world length that comes out at most 100px wide at the current zoom
+        let length = nice_length(100.0 / self.zoom);
+        let bar_width = length * self.zoom;
+        
+        // Sit just left of the minimap in the bottom-right corner
+        let right = if self.show_minimap {
+            self.minimap_layout(game_state.map_size).0 - 10.0
+        } else {
+            self.view_width() - 10.0
+        };
+        let left = right - bar_width;
+        let bottom = self.view_height() - 10.0;
+        
         self.context.save();
         
-        // Apply camera transform
-        self.context.translate(-self.camera_x * self.zoom, -self.camera_y * self.zoom).unwrap();
-        self.context.scale(self.zoom, self.zoom).unwrap();
+        // Draw the bar with end ticks
+        self.context.set_stroke_style(&JsValue::from_str("#ffffff"));
+        self.context.set_line_width(2.0);
+        self.context.begin_path();
+        self.context.move_to(left, bottom - 6.0);
+        self.context.line_to(left, bottom);
+        self.context.line_to(right, bottom);
+        self.context.line_to(right, bottom - 6.0);
+        self.context.stroke();
         
-        // Draw troops
-        for troop in &game_state.troops {
-            let (x, y) = troop.position;
-            let (r, g, b) = troop.color;
-            let color = format!("rgb({}, {}, {})", r, g, b);
-            let size = 10.0;
-            
-            self.context.save();
-            self.context.translate(x, y).unwrap();
-            
-            // Draw selection indicator for selected troops
-            if self.selected_troops.contains(&troop.id) {
-                self.context.set_stroke_style(&JsValue::from_str("#00ff00"));
-                self.context.set_line_width(2.0);
-                
-                // Draw selection circle
-                self.context.begin_path();
-                self.context.arc(0.0, 0.0, size * 0.8, 0.0, 2.0 * PI).unwrap();
-                self.context.stroke();
-            }
-            
-            // Draw health bar
-            let health_width = size * 1.5;
-            let health_height = 2.0;
-            let health_y = -size - 5.0;
-            
-            self.context.set_fill_style(&JsValue::from_str("#ff0000"));
-            self.context.fill_rect(-health_width/2.0, health_y, health_width, health_height);
-            
-            self.context.set_fill_style(&JsValue::from_str("#00ff00"));
-            let health_percent = troop.health / 100.0;
-            self.context.fill_rect(-health_width/2.0, health_y, health_width * health_percent, health_height);
-            
-            // Draw troop shape
-            self.context.set_fill_style(&JsValue::from_str(&color));
-            
-            match troop.shape.as_str() {
-                "circle" => {
-                    self.context.begin_path();
-                    self.context.arc(0.0, 0.0, size/2.0, 0.0, 2.0 * PI).unwrap();
-                    self.context.fill();
-                },
-                "square" => {
-                    self.context.fill_rect(-size/2.0, -size/2.0, size, size);
-                },
-                "triangle" => {
-                    self.context.begin_path();
-                    self.context.move_to(0.0, -size/2.0);
-                    self.context.line_to(size/2.0, size/2.0);
-                    self.context.line_to(-size/2.0, size/2.0);
-                    self.context.close_path();
-                    self.context.fill();
-                },
-                _ => {
-                    self.context.fill_rect(-size/2.0, -size/2.0, size, size);
-                }
-            }
-            
-            // Draw direction indicator
-            let (dx, dy) = troop.direction;
-            self.context.set_stroke_style(&JsValue::from_str("#ffffff"));
-            self.context.set_line_width(1.0);
-            self.context.begin_path();
-            self.context.move_to(0.0, 0.0);
-            self.context.line_to(dx * size, dy * size);
-            self.context.stroke();
-            
-            self.context.restore();
-        }
+        // Label the distance above the bar
+        self.context.set_font("12px Arial");
+        self.context.set_text_align("center");
+        self.context.set_fill_style(&JsValue::from_str("#ffffff"));
+        self.context.fill_text(&format!("{} units", length), left + bar_width / 2.0, bottom - 10.0).unwrap();
         
         self.context.restore();
     }
     
-    fn render_projectiles(&self, game_state: &GameState) {
+    fn minimap_layout(&self, map_size: (f64, f64)) -> (f64, f64, f64) {
+        // Fit the map into a fixed 150x150 box in the bottom-right corner
+        let minimap_size = 150.0;
+        let margin = 10.0;
+        let (map_width, map_height) = map_size;
+        let scale = (minimap_size / map_width).min(minimap_size / map_height);
+        
+        let origin_x = self.view_width() - margin - map_width * scale;
+        let origin_y = self.view_height() - margin - map_height * scale;
+        
+        (origin_x, origin_y, scale)
+    }
+    
+    fn render_minimap(&self, game_state: &GameState) {
+        let (map_width, map_height) = game_state.map_size;
+        let (origin_x, origin_y, scale) = self.minimap_layout(game_state.map_size);
+        
         self.context.save();
         
-        // Apply camera transform
-        self.context.translate(-self.camera_x * self.zoom, -self.camera_y * self.zoom).unwrap();
-        self.context.scale(self.zoom, self.zoom).unwrap();
+        // Draw minimap background
+        self.context.set_fill_style(&JsValue::from_str("rgba(0, 0, 0, 0.7)"));
+        self.context.fill_rect(origin_x, origin_y, map_width * scale, map_height * scale);
         
-        // Draw projectiles
-        for projectile in &game_state.projectiles {
-            let (x, y) = projectile.position;
-            let (r, g, b) = projectile.color;
-            let color = format!("rgb({}, {}, {})", r, g, b);
-            
-            self.context.save();
-            self.context.translate(x, y).unwrap();
-            
-            // Draw arrow
-            self.context.set_fill_style(&JsValue::from_str(&color));
-            
-            // Rotate context to match arrow direction
-            let (dx, dy) = projectile.direction;
-            let angle = dy.atan2(dx);
-            self.context.rotate(angle).unwrap();
-            
-            // Draw arrow body
-            let arrow_length = 8.0;
-            let arrow_width = 2.0;
-            
-            // Arrow shaft
-            self.context.fill_rect(-arrow_length/2.0, -arrow_width/2.0, arrow_length, arrow_width);
-            
-            // Arrow head
-            self.context.begin_path();
-            self.context.move_to(arrow_length/2.0, 0.0);
-            self.context.line_to(arrow_length/2.0 - 4.0, -4.0);
-            self.context.line_to(arrow_length/2.0 - 4.0, 4.0);
-            self.context.close_path();
-            self.context.fill();
+        // Clip everything else to the minimap area
+        self.context.begin_path();
+        self.context.rect(origin_x, origin_y, map_width * scale, map_height * scale);
+        self.context.clip();
+        
+        // Draw each troop as a single pixel
+        for troop in &game_state.troops {
+            if !self.is_troop_visible(game_state, troop) {
+                continue;
+            }
             
-            self.context.restore();
+            let (x, y) = troop.position;
+            let (r, g, b) = self.apply_colorblind(troop.color);
+            self.context.set_fill_style(&JsValue::from_str(&format!("rgb({}, {}, {})", r, g, b)));
+            self.context.fill_rect(origin_x + x * scale, origin_y + y * scale, 1.0, 1.0);
+        }
+        
+        // Draw camera viewport, which is a rotated quad when the camera is rotated
+        self.context.set_stroke_style(&JsValue::from_str("#ffffff"));
+        self.context.set_line_width(1.0);
+        self.context.begin_path();
+        for (i, (x, y)) in self.visible_world_corners().iter().enumerate() {
+            if i == 0 {
+                self.context.move_to(origin_x + x * scale, origin_y + y * scale);
+            } else {
+                self.context.line_to(origin_x + x * scale, origin_y + y * scale);
+            }
         }
+        self.context.close_path();
+        self.context.stroke();
+        
+        self.context.restore();
         
+        // Draw map border
+        self.context.save();
+        self.context.set_stroke_style(&JsValue::from_str(&self.theme.border));
+        self.context.set_line_width(1.0);
+        self.context.stroke_rect(origin_x, origin_y, map_width * scale, map_height * scale);
         self.context.restore();
     }
     
@@ -579,12 +4696,12 @@ impl Renderer {
         if let Some(dev_data) = &self.dev_data {
             self.context.save();
             
-            let width = self.canvas.width() as f64;
-            let height = self.canvas.height() as f64;
+            let width = self.view_width();
+            let height = self.view_height();
             
             // Draw dev tools panel
             self.context.set_fill_style(&JsValue::from_str("rgba(0, 0, 0, 0.7)"));
-            self.context.fill_rect(10.0, 10.0, 200.0, 150.0);
+            self.context.fill_rect(10.0, 10.0, 200.0, 290.0);
             
             self.context.set_font("14px Arial");
             self.context.set_fill_style(&JsValue::from_str("#ffffff"));
@@ -609,9 +4726,60 @@ impl Renderer {
                 self.context.fill_text(&format!("Player ID: {}", player_id), 20.0, 130.0).unwrap();
             }
             
+            // World position under the mouse
+            if let Some((mouse_x, mouse_y)) = self.mouse_world_position {
+                self.context.fill_text(&format!("Mouse: ({:.0}, {:.0})", mouse_x, mouse_y), 20.0, 150.0).unwrap();
+            }
+            
+            // What the last frame drew versus culled, and the troop and projectile draw calls
+            let stats = self.render_stats.get();
+            let troop_total = stats.troops_drawn + stats.troops_culled;
+            let projectile_total = stats.projectiles_drawn + stats.projectiles_culled;
+            self.context.fill_text(&format!("Troops {}/{}, culled {}", stats.troops_drawn, troop_total, stats.troops_culled), 20.0, 170.0).unwrap();
+            self.context.fill_text(&format!("Shots {}/{}, culled {}", stats.projectiles_drawn, projectile_total, stats.projectiles_culled), 20.0, 190.0).unwrap();
+            self.context.fill_text(&format!("Draw calls: {}", stats.draw_calls), 20.0, 210.0).unwrap();
+            
+            // Entities dropped from incoming states for non-finite values
+            self.context.fill_text(&format!("Rejected: {}", self.rejected_entities), 20.0, 230.0).unwrap();
+            
+            // Frame time graph
+            self.render_frame_time_graph(20.0, 245.0, 180.0, 45.0);
+            
             self.context.restore();
         }
     }
+    
+    fn render_frame_time_graph(&self, x: f64, y: f64, width: f64, height: f64) {
+        let frame_budget = 16.6;
+        let max_frame_time = 50.0;
+        let step = width / 119.0;
+        let to_y = |frame_time: f64| y + height - frame_time.min(max_frame_time) / max_frame_time * height;
+        
+        self.context.save();
+        
+        // Draw frame budget line
+        self.context.set_stroke_style(&JsValue::from_str("#666666"));
+        self.context.set_line_width(1.0);
+        self.context.begin_path();
+        self.context.move_to(x, to_y(frame_budget));
+        self.context.line_to(x + width, to_y(frame_budget));
+        self.context.stroke();
+        
+        // Draw frame times, highlighting frames over budget
+        for i in 1..self.frame_times.len() {
+            let previous = self.frame_times[i - 1];
+            let current = self.frame_times[i];
+            let color = if current > frame_budget { "#ff0000" } else { "#00ff00" };
+            
+            self.context.set_stroke_style(&JsValue::from_str(color));
+            self.context.begin_path();
+            self.context.move_to(x + (i - 1) as f64 * step, to_y(previous));
+            self.context.line_to(x + i as f64 * step, to_y(current));
+            self.context.stroke();
+        }
+        
+        self.context.restore();
+    }
 }
 
 // Helper function to convert a tuple to a JS array
@@ -621,3 +4789,545 @@ fn array_from_tuple(tuple: (f64, f64)) -> Array {
     array.push(&JsValue::from_f64(tuple.1));
     array
 }
+
+// Helper function to clamp one camera axis to the map, centering the map when the view is larger
+fn clamp_camera_axis(camera: f64, map_extent: f64, view_extent: f64) -> f64 {
+    if view_extent >= map_extent {
+        (map_extent - view_extent) / 2.0
+    } else {
+        camera.max(0.0).min(map_extent - view_extent)
+    }
+}
+
+// Helper function to get a high-resolution timestamp in milliseconds
+fn now() -> f64 {
+    web_sys::window().unwrap().performance().unwrap().now()
+}
+
+// Helper function to get the rotation angle that points along a direction vector
+fn facing_angle(direction: (f64, f64)) -> f64 {
+    let (dx, dy) = direction;
+    dy.atan2(dx)
+}
+
+// Helper function to rotate a point around the origin
+fn rotate_point(point: (f64, f64), angle: f64) -> (f64, f64) {
+    let (x, y) = point;
+    let (sin, cos) = angle.sin_cos();
+    (x * cos - y * sin, x * sin + y * cos)
+}
+
+// Helper function to capitalize the first letter of a string
+fn capitalize(text: &str) -> String {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+// Helper function to lay out a roughly square grid of offsets centered on the origin
+fn formation_offsets(count: usize, spacing: f64) -> Vec<(f64, f64)> {
+    if count == 0 {
+        return Vec::new();
+    }
+    
+    let columns = (count as f64).sqrt().ceil() as usize;
+    let rows = count.div_ceil(columns);
+    
+    (0..count)
+        .map(|i| {
+            let column = (i % columns) as f64 - (columns - 1) as f64 / 2.0;
+            let row = (i / columns) as f64 - (rows - 1) as f64 / 2.0;
+            (column * spacing, row * spacing)
+        })
+        .collect()
+}
+
+// Helper function to linearly blend one color toward another by t in 0..1
+fn blend_color(from: (u8, u8, u8), to: (u8, u8, u8), t: f64) -> (u8, u8, u8) {
+    let t = t.clamp(0.0, 1.0);
+    let mix = |a: u8, b: u8| (a as f64 + (b as f64 - a as f64) * t).round() as u8;
+    (mix(from.0, to.0), mix(from.1, to.1), mix(from.2, to.2))
+}
+
+// Helper function to compare two troop ID lists as sets
+fn same_troop_set(a: &[u32], b: &[u32]) -> bool {
+    let mut a = a.to_vec();
+    let mut b = b.to_vec();
+    a.sort_unstable();
+    b.sort_unstable();
+    a == b
+}
+
+// Helper function to test whether a point lies inside a box, edges included
+fn in_rect(point: (f64, f64), min: (f64, f64), max: (f64, f64)) -> bool {
+    point.0 >= min.0 && point.0 <= max.0 && point.1 >= min.1 && point.1 <= max.1
+}
+
+// Helper function to round a length down to 1, 2 or 5 times a power of ten
+fn nice_length(max: f64) -> f64 {
+    let magnitude = 10f64.powf(max.log10().floor());
+    [5.0, 2.0, 1.0].iter()
+        .map(|step| step * magnitude)
+        .find(|length| *length <= max)
+        .unwrap_or(magnitude)
+}
+
+// Helper function to find the radial command menu slice an angle points into
+fn command_slice_at(angle: f64) -> usize {
+    let slice_angle = 2.0 * PI / COMMAND_MENU_SLICES.len() as f64;
+    let offset = (angle + slice_angle / 2.0).rem_euclid(2.0 * PI);
+    ((offset / slice_angle) as usize).min(COMMAND_MENU_SLICES.len() - 1)
+}
+
+// Helper function to snap a point to the center of its pointy-top hex, hexes being width wide
+fn snap_to_hex(point: (f64, f64), width: f64) -> (f64, f64) {
+    let radius = width / 3f64.sqrt();
+    let (x, y) = point;
+    
+    // Fractional axial coordinates, rounded through cube coordinates to the nearest hex
+    let q = (x * 3f64.sqrt() / 3.0 - y / 3.0) / radius;
+    let r = (y * 2.0 / 3.0) / radius;
+    let s = -q - r;
+    
+    let (mut rounded_q, mut rounded_r, rounded_s) = (q.round(), r.round(), s.round());
+    let (q_error, r_error, s_error) = ((rounded_q - q).abs(), (rounded_r - r).abs(), (rounded_s - s).abs());
+    if q_error > r_error && q_error > s_error {
+        rounded_q = -rounded_r - rounded_s;
+    } else if r_error > s_error {
+        rounded_r = -rounded_q - rounded_s;
+    }
+    
+    (width * (rounded_q + rounded_r / 2.0), radius * 1.5 * rounded_r)
+}
+
+// Helper function to derive a stable offset in -1..1 on each axis from an ID
+fn id_jitter(id: u32) -> (f64, f64) {
+    // SplitMix64 finalizer, so neighboring IDs land far apart
+    let mut hash = (id as u64).wrapping_add(0x9e3779b97f4a7c15);
+    hash = (hash ^ (hash >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    hash = (hash ^ (hash >> 27)).wrapping_mul(0x94d049bb133111eb);
+    hash ^= hash >> 31;
+    
+    let unit = |bits: u64| (bits & 0xffff) as f64 / 65535.0 * 2.0 - 1.0;
+    (unit(hash), unit(hash >> 16))
+}
+
+// Helper function to compute a troop's outline around a center, with triangles pointing along angle
+fn shape_path(shape: &str, troop: &Troop, center: (f64, f64), angle: f64) -> ShapePath {
+    let (x, y) = center;
+    let size = troop.size();
+    let square = |side: f64| ShapePath::Rect { origin: (x - side/2.0, y - side/2.0), size: (side, side) };
+    
+    match shape {
+        "circle" => ShapePath::Circle { center, radius: size/2.0 },
+        "triangle" => {
+            let corners = [(size/2.0, 0.0), (-size/2.0, -size/2.0), (-size/2.0, size/2.0)];
+            ShapePath::Triangle(corners.map(|corner| {
+                let (dx, dy) = rotate_point(corner, angle);
+                (x + dx, y + dy)
+            }))
+        },
+        "rectangle" => {
+            let width = troop.width.unwrap_or(size);
+            let height = troop.height.unwrap_or(size);
+            ShapePath::Rect { origin: (x - width/2.0, y - height/2.0), size: (width, height) }
+        },
+        "dot" => square(size/2.0),
+        "diamond" => ShapePath::Quad([
+            (x, y - size/2.0),
+            (x + size/2.0, y),
+            (x, y + size/2.0),
+            (x - size/2.0, y),
+        ]),
+        _ => square(size),
+    }
+}
+
+// Helper function to remove an already selected troop from a selection, or add it otherwise
+fn toggled_selection(selection: &[u32], troop_id: u32) -> Vec<u32> {
+    let mut selection = selection.to_vec();
+    if let Some(index) = selection.iter().position(|&id| id == troop_id) {
+        selection.remove(index);
+    } else {
+        selection.push(troop_id);
+    }
+    selection
+}
+
+// Helper function to collect the troops inside a box, optionally only those of one player
+fn troops_in_box(troops: &[Troop], min: (f64, f64), max: (f64, f64), owner: Option<u32>) -> Vec<&Troop> {
+    troops.iter()
+        .filter(|t| owner.is_none() || owner == Some(t.player_id))
+        .filter(|t| in_rect(t.position, min, max))
+        .collect()
+}
+
+// Helper function to test whether a point lies within a view rectangle grown by a margin.
+// Render passes compute the visible rectangle once and cull each entity against it.
+fn in_view(view: (f64, f64, f64, f64), point: (f64, f64), margin: f64) -> bool {
+    let (min_x, min_y, max_x, max_y) = view;
+    in_rect(point, (min_x - margin, min_y - margin), (max_x + margin, max_y + margin))
+}
+
+// Helper function to drop ranges that canvas arc() would reject
+fn drawable_range(range: Option<f64>) -> Option<f64> {
+    range.filter(|r| r.is_finite() && *r >= 0.0)
+}
+
+// Helper function to check for a troop of the given player within a radius, through the
+// spatial index when the troops have one
+fn any_friendly_within(troops: &[Troop], index: Option<&TroopIndex>, player_id: u32, position: (f64, f64), radius: f64) -> bool {
+    let is_near = |t: &Troop| {
+        let dx = position.0 - t.position.0;
+        let dy = position.1 - t.position.1;
+        t.player_id == player_id && dx * dx + dy * dy <= radius * radius
+    };
+    match index {
+        Some(index) => index.query(position.0, position.1, radius).into_iter()
+            .filter_map(|i| troops.get(i))
+            .any(is_near),
+        None => troops.iter().any(is_near),
+    }
+}
+
+// Helper function to parse an optional setting name, rejecting unknown names like the setters do
+fn parse_name<T>(name: &Option<String>, from_name: fn(&str) -> Option<T>, kind: &str) -> Result<Option<T>, JsValue> {
+    name.as_deref()
+        .map(|name| from_name(name).ok_or_else(|| JsValue::from_str(&format!("Unknown {}: {}", kind, name))))
+        .transpose()
+}
+
+// Helper function to space major grid lines every 5, 10, 50, 100, ... cells, at least 20 pixels apart
+fn major_grid_step(grid_size: f64, zoom: f64) -> f64 {
+    let mut step = grid_size * 5.0;
+    let mut factor = 2.0;
+    while step * zoom < 20.0 && step.is_finite() {
+        step *= factor;
+        factor = if factor == 2.0 { 5.0 } else { 2.0 };
+    }
+    step
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn assert_close(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < 1e-9, "expected {}, got {}", expected, actual);
+    }
+    
+    fn troop(id: u32, x: f64, y: f64) -> Troop {
+        Troop {
+            id,
+            player_id: 1,
+            position: (x, y),
+            direction: (0.0, 0.0),
+            speed: 0.0,
+            health: 100.0,
+            attack: 10.0,
+            color: (255, 255, 255),
+            shape: "circle".to_string(),
+            unit_type: "basic".to_string(),
+            is_attacking: false,
+            weight: 1.0,
+            attack_speed: None,
+            attack_range: None,
+            attack_cooldown: None,
+            attack_rate: None,
+            max_speed: None,
+            acceleration: None,
+            min_range: None,
+            max_range: None,
+            target: None,
+            target_position: None,
+            max_health: None,
+            width: None,
+            height: None,
+            is_stealthed: false,
+            render_size: None,
+            rank: None,
+            name: None,
+        }
+    }
+    
+    // Deterministic pseudo-random numbers in 0..1, so failures reproduce
+    struct Lcg(u64);
+    
+    impl Lcg {
+        fn next(&mut self) -> f64 {
+            self.0 = self.0.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (self.0 >> 11) as f64 / (1u64 << 53) as f64
+        }
+    }
+    
+    #[test]
+    fn facing_angle_matches_atan2() {
+        let directions = [(1.0, 0.0), (0.0, 1.0), (-1.0, 0.0), (0.0, -1.0), (3.0, 4.0), (-2.0, -5.0), (0.5, -0.5)];
+        for (dx, dy) in directions {
+            assert_close(facing_angle((dx, dy)), dy.atan2(dx));
+        }
+    }
+    
+    #[test]
+    fn rotate_point_turns_counterclockwise_in_math_axes() {
+        let (x, y) = rotate_point((1.0, 0.0), PI / 2.0);
+        assert_close(x, 0.0);
+        assert_close(y, 1.0);
+        
+        let (x, y) = rotate_point((2.0, 1.0), PI);
+        assert_close(x, -2.0);
+        assert_close(y, -1.0);
+    }
+    
+    #[test]
+    fn rotate_point_to_facing_angle_points_along_direction() {
+        // Triangles are drawn by rotating their tip by the facing angle
+        for (dx, dy) in [(1.0, 1.0), (-3.0, 4.0), (0.0, -2.0)] {
+            let length = f64::hypot(dx, dy);
+            let (x, y) = rotate_point((length, 0.0), facing_angle((dx, dy)));
+            assert_close(x, dx);
+            assert_close(y, dy);
+        }
+    }
+    
+    #[test]
+    fn troop_index_query_finds_same_nearest_troop_as_brute_force() {
+        let mut rng = Lcg(42);
+        
+        // Spread troops over negative coordinates too, where cells rely on floor()
+        let troops: Vec<Troop> = (0..500)
+            .map(|id| troop(id, rng.next() * 1000.0 - 500.0, rng.next() * 1000.0 - 500.0))
+            .collect();
+        let mut index = TroopIndex::new(50.0);
+        index.rebuild(&troops);
+        
+        let distance = |t: &Troop, x: f64, y: f64| (t.position.0 - x).powi(2) + (t.position.1 - y).powi(2);
+        let nearest = |candidates: Vec<&Troop>, x: f64, y: f64, radius: f64| {
+            candidates.into_iter()
+                .filter(|t| distance(t, x, y) <= radius * radius)
+                .min_by(|a, b| distance(a, x, y).total_cmp(&distance(b, x, y)))
+                .map(|t| t.id)
+        };
+        
+        for _ in 0..500 {
+            let x = rng.next() * 1100.0 - 550.0;
+            let y = rng.next() * 1100.0 - 550.0;
+            let radius = rng.next() * 80.0;
+            
+            let indexed = index.query(x, y, radius).into_iter().map(|i| &troops[i]).collect();
+            let brute_force = troops.iter().collect();
+            assert_eq!(nearest(indexed, x, y, radius), nearest(brute_force, x, y, radius));
+        }
+    }
+    
+    #[test]
+    fn troop_index_cells_floor_negative_coordinates() {
+        let index = TroopIndex::new(50.0);
+        assert_eq!(index.cell_of(-0.5, -50.0), (-1, -1));
+        assert_eq!(index.cell_of(-50.5, 0.0), (-2, 0));
+        assert_eq!(index.cell_of(49.9, 50.0), (0, 1));
+    }
+    
+    #[test]
+    fn shape_path_traces_each_shape() {
+        let mut unit = troop(1, 0.0, 0.0);
+        unit.render_size = Some(20.0);
+        let center = (100.0, 50.0);
+        
+        assert_eq!(shape_path("circle", &unit, center, 0.0), ShapePath::Circle { center, radius: 10.0 });
+        assert_eq!(shape_path("square", &unit, center, 0.0), ShapePath::Rect { origin: (90.0, 40.0), size: (20.0, 20.0) });
+        assert_eq!(shape_path("dot", &unit, center, 0.0), ShapePath::Rect { origin: (95.0, 45.0), size: (10.0, 10.0) });
+        assert_eq!(shape_path("unknown", &unit, center, 0.0), ShapePath::Rect { origin: (90.0, 40.0), size: (20.0, 20.0) });
+        assert_eq!(
+            shape_path("diamond", &unit, center, 0.0),
+            ShapePath::Quad([(100.0, 40.0), (110.0, 50.0), (100.0, 60.0), (90.0, 50.0)]),
+        );
+        
+        unit.width = Some(30.0);
+        unit.height = Some(6.0);
+        assert_eq!(shape_path("rectangle", &unit, center, 0.0), ShapePath::Rect { origin: (85.0, 47.0), size: (30.0, 6.0) });
+    }
+    
+    #[test]
+    fn shape_path_points_triangles_along_angle() {
+        let mut unit = troop(1, 0.0, 0.0);
+        unit.render_size = Some(20.0);
+        
+        let points = match shape_path("triangle", &unit, (0.0, 0.0), PI / 2.0) {
+            ShapePath::Triangle(points) => points,
+            other => panic!("expected a triangle, got {:?}", other),
+        };
+        
+        // Facing straight down the screen, the tip is below the center and the base above it
+        let expected = [(0.0, 10.0), (10.0, -10.0), (-10.0, -10.0)];
+        for ((x, y), (expected_x, expected_y)) in points.iter().zip(expected) {
+            assert_close(*x, expected_x);
+            assert_close(*y, expected_y);
+        }
+    }
+    
+    #[test]
+    fn toggled_selection_adds_removes_and_re_adds() {
+        let selection = toggled_selection(&[1, 2], 3);
+        assert_eq!(selection, vec![1, 2, 3]);
+        
+        let selection = toggled_selection(&selection, 2);
+        assert_eq!(selection, vec![1, 3]);
+        
+        let selection = toggled_selection(&selection, 2);
+        assert_eq!(selection, vec![1, 3, 2]);
+        
+        let selection = toggled_selection(&selection, 1);
+        let selection = toggled_selection(&selection, 3);
+        let selection = toggled_selection(&selection, 2);
+        assert!(selection.is_empty());
+    }
+    
+    #[test]
+    fn selection_drag_uses_the_camera_at_release_after_a_pan() {
+        let camera = Camera { x: 0.0, y: 0.0, zoom: 2.0, rotation: 0.0, view_width: 800.0, view_height: 600.0 };
+        
+        // The drag box stays put on screen while the camera pans 50 world units right
+        let start = (180.0, 180.0);
+        let end = (220.0, 220.0);
+        let panned = Camera { x: 50.0, ..camera };
+        
+        // The troop under the box before the pan has slid out of it, and the one drawn
+        // under the box after the pan is selected
+        let before = (100.0, 100.0);
+        let after = (150.0, 100.0);
+        assert!(SelectionMode::Box.contains(start, end, camera.world_to_screen(before.0, before.1)));
+        assert!(!SelectionMode::Box.contains(start, end, panned.world_to_screen(before.0, before.1)));
+        assert!(SelectionMode::Box.contains(start, end, panned.world_to_screen(after.0, after.1)));
+        assert!(SelectionMode::Circle.contains((200.0, 200.0), (215.0, 200.0), panned.world_to_screen(after.0, after.1)));
+    }
+    
+    #[test]
+    fn camera_conversions_round_trip_with_rotation() {
+        let camera = Camera { x: -30.0, y: 12.0, zoom: 1.5, rotation: 0.7, view_width: 640.0, view_height: 480.0 };
+        for (x, y) in [(0.0, 0.0), (320.0, 240.0), (-50.0, 900.0)] {
+            let (world_x, world_y) = camera.screen_to_world(x, y);
+            let (screen_x, screen_y) = camera.world_to_screen(world_x, world_y);
+            assert_close(screen_x, x);
+            assert_close(screen_y, y);
+        }
+    }
+    
+    #[test]
+    fn in_rect_includes_edges_and_corners() {
+        let (min, max) = ((0.0, 0.0), (10.0, 20.0));
+        for point in [(0.0, 0.0), (10.0, 20.0), (0.0, 20.0), (10.0, 0.0), (5.0, 0.0), (10.0, 7.0)] {
+            assert!(in_rect(point, min, max), "{:?} should be inside", point);
+        }
+        for point in [(-0.001, 5.0), (10.001, 5.0), (5.0, -0.001), (5.0, 20.001)] {
+            assert!(!in_rect(point, min, max), "{:?} should be outside", point);
+        }
+    }
+    
+    #[test]
+    fn troops_in_box_filters_by_edges_and_owner() {
+        let mut enemy = troop(3, 10.0, 10.0);
+        enemy.player_id = 2;
+        let troops = vec![troop(1, 0.0, 0.0), troop(2, 10.0, 10.0), enemy, troop(4, 10.5, 10.0)];
+        
+        let ids = |found: Vec<&Troop>| found.iter().map(|t| t.id).collect::<Vec<_>>();
+        assert_eq!(ids(troops_in_box(&troops, (0.0, 0.0), (10.0, 10.0), None)), vec![1, 2, 3]);
+        assert_eq!(ids(troops_in_box(&troops, (0.0, 0.0), (10.0, 10.0), Some(2))), vec![3]);
+        assert_eq!(ids(troops_in_box(&troops, (0.0, 0.0), (10.0, 10.0), Some(1))), vec![1, 2]);
+    }
+    
+    #[test]
+    fn size_falls_back_for_non_positive_render_size() {
+        let mut t = troop(1, 0.0, 0.0);
+        t.render_size = Some(0.0);
+        assert_eq!(t.size(), 10.0);
+        t.render_size = Some(-4.0);
+        assert_eq!(t.size(), 10.0);
+        t.render_size = Some(6.0);
+        assert_eq!(t.size(), 6.0);
+    }
+    
+    #[test]
+    fn drawable_range_rejects_negative_and_non_finite() {
+        assert_eq!(drawable_range(Some(-1.0)), None);
+        assert_eq!(drawable_range(Some(f64::NAN)), None);
+        assert_eq!(drawable_range(Some(f64::INFINITY)), None);
+        assert_eq!(drawable_range(Some(0.0)), Some(0.0));
+        assert_eq!(drawable_range(Some(50.0)), Some(50.0));
+        assert_eq!(drawable_range(None), None);
+    }
+    
+    #[test]
+    fn any_friendly_within_matches_with_and_without_index() {
+        let mut rng = Lcg(7);
+        let troops: Vec<Troop> = (0..300)
+            .map(|id| {
+                let mut t = troop(id, rng.next() * 1000.0 - 500.0, rng.next() * 1000.0 - 500.0);
+                t.player_id = id % 3;
+                t
+            })
+            .collect();
+        let mut index = TroopIndex::new(50.0);
+        index.rebuild(&troops);
+        
+        for _ in 0..500 {
+            let position = (rng.next() * 1100.0 - 550.0, rng.next() * 1100.0 - 550.0);
+            let radius = rng.next() * 150.0;
+            assert_eq!(
+                any_friendly_within(&troops, Some(&index), 1, position, radius),
+                any_friendly_within(&troops, None, 1, position, radius),
+            );
+        }
+    }
+    
+    #[test]
+    fn snap_to_hex_lands_on_nearest_drawn_hex_center() {
+        // Centers as render_hex_grid lays them out
+        let width = 40.0;
+        let row_height = width / 3f64.sqrt() * 1.5;
+        let center = |row: i64, column: i64| {
+            let shift = if row.rem_euclid(2) == 1 { width / 2.0 } else { 0.0 };
+            (column as f64 * width + shift, row as f64 * row_height)
+        };
+        let distance = |a: (f64, f64), b: (f64, f64)| f64::hypot(a.0 - b.0, a.1 - b.1);
+        
+        let mut rng = Lcg(3);
+        for _ in 0..1000 {
+            let point = (rng.next() * 800.0 - 400.0, rng.next() * 800.0 - 400.0);
+            let snapped = snap_to_hex(point, width);
+            
+            let row = (point.1 / row_height).floor() as i64;
+            let column = (point.0 / width).floor() as i64;
+            let nearest = (row - 1..=row + 2)
+                .flat_map(|r| (column - 1..=column + 2).map(move |c| (r, c)))
+                .map(|(r, c)| center(r, c))
+                .min_by(|a, b| distance(point, *a).total_cmp(&distance(point, *b)))
+                .unwrap();
+            
+            assert_close(snapped.0, nearest.0);
+            assert_close(snapped.1, nearest.1);
+        }
+    }
+    
+    #[test]
+    fn rotated_camera_excludes_bounding_box_corners() {
+        let camera = Camera { x: 0.0, y: 0.0, zoom: 1.0, rotation: PI / 4.0, view_width: 400.0, view_height: 400.0 };
+        let (min_x, min_y, _, _) = camera.visible_world_rect();
+        
+        // The bounding box corner lies outside the rotated canvas, its center inside
+        assert!(!camera.is_on_screen(min_x + 1.0, min_y + 1.0));
+        assert!(camera.is_on_screen(200.0, 200.0));
+    }
+    
+    #[test]
+    fn major_grid_step_keeps_lines_apart_when_zoomed_out() {
+        assert_eq!(major_grid_step(50.0, 1.0), 250.0);
+        assert_eq!(major_grid_step(50.0, 0.05), 500.0);
+        assert_eq!(major_grid_step(1.0, 0.2), 100.0);
+        for zoom in [0.01, 0.2, 0.5, 1.0, 5.0] {
+            assert!(major_grid_step(1.0, zoom) * zoom >= 20.0);
+        }
+    }
+}